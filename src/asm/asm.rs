@@ -1,9 +1,12 @@
-use crate::asm::config::{RVOpCode, RVRegCode, RVREG_ALLOCATOR, RegAllocType, STK_FRM_MANAGER};
+use crate::asm::config::{
+    arg_locations, ArgLoc, RVFRegCode, RVOpCode, RVRegCode, RegAllocType, IMM12_MAX, IMM12_MIN,
+    AsmContext,
+};
+use crate::ast::exp::IRObj;
 use crate::koopa_ir::config::KoopaOpCode;
 use crate::koopa_ir::koopa_ir::{Func, InstData, Operand, Program};
-use crate::config::config::CONTEXT_STACK;
+use crate::config::config::BType;
 
-use std::rc::Rc;
 
 pub struct Asm {
     pub global_vals: Vec<AsmGlobalVal>,
@@ -15,7 +18,7 @@ impl std::fmt::Display for Asm {
         // data section
         writeln!(f, ".data")?;
         for val in &self.global_vals {
-            writeln!(f, ".global {}", val)?;
+            writeln!(f, ".global {}", val.name)?;
         }
 
         for val in &self.global_vals {
@@ -37,6 +40,12 @@ impl std::fmt::Display for Asm {
     }
 }
 
+impl Default for Asm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Asm {
     pub fn new() -> Self {
         Self {
@@ -50,23 +59,16 @@ impl Asm {
 
         // add global_syms
         for val in &program.global_vals {
-            asm.global_vals
-                .push(AsmGlobalVal::new(val.name.clone(), val.val));
+            // floats are emitted as their bits
+            let init = val.init.iter().map(|value| value.to_bits()).collect();
+            asm.global_vals.push(AsmGlobalVal::new(val.name.clone(), init));
         }
 
         // add blocks
+        let ctx = &mut AsmContext::new(program);
         for func in &program.funcs {
-            CONTEXT_STACK.with(|stack| {
-                let mut stack = stack.borrow_mut();
-                stack.enter_func_scope(Rc::clone(&func));
-            });
-
-            asm.blocks.push(AsmBlock::from(&func));
-
-            CONTEXT_STACK.with(|stack| {
-                let mut stack = stack.borrow_mut();
-                stack.exit_scope();
-            });
+            ctx.enter_func(func);
+            asm.blocks.push(AsmBlock::from(ctx, func));
         }
 
         Ok(asm)
@@ -75,33 +77,57 @@ impl Asm {
 
 pub struct AsmGlobalVal {
     pub name: String,
-    pub val: i32,
+    pub init: Vec<i32>,
 }
 
 impl std::fmt::Display for AsmGlobalVal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "{}:", self.name)?;
-        writeln!(f, "    .word {}", self.val)?;
+
+        // consecutive zeros are merged into a single .zero
+        let mut zeros = 0;
+        for val in &self.init {
+            if *val == 0 {
+                zeros += 1;
+                continue;
+            }
+            if zeros > 0 {
+                writeln!(f, "    .zero {}", zeros * 4)?;
+                zeros = 0;
+            }
+            writeln!(f, "    .word {}", val)?;
+        }
+        if zeros > 0 {
+            writeln!(f, "    .zero {}", zeros * 4)?;
+        }
         Ok(())
     }
 }
 
 impl AsmGlobalVal {
-    pub fn new(name: String, val: i32) -> Self {
-        Self { name, val }
+    pub fn new(name: String, init: Vec<i32>) -> Self {
+        Self { name, init }
     }
 }
 
 pub struct AsmBlock {
     pub label: String,
     pub insts: Vec<AsmInst>,
+    // labeled basic blocks following the entry block of a function
+    pub blocks: Vec<AsmBlock>,
 }
 
 impl std::fmt::Display for AsmBlock {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "{}:", self.label)?;
         for inst in &self.insts {
-            write!(f, "    {}", inst)?;
+            match inst.opcode {
+                RVOpCode::LABEL => write!(f, "{}", inst)?,
+                _ => write!(f, "    {}", inst)?,
+            }
+        }
+        for block in &self.blocks {
+            write!(f, "{}", block)?;
         }
         Ok(())
     }
@@ -112,51 +138,37 @@ impl AsmBlock {
         Self {
             label,
             insts: Vec::new(),
+            blocks: Vec::new(),
         }
     }
 
-    pub fn from(func: &Func) -> Self {
+    pub fn from(ctx: &mut AsmContext, func: &Func) -> Self {
         let mut asm_block = AsmBlock::new(func.name.clone());
 
-        // TODO: for now, we don't need to process funct_type and params
-
         // add prologue
-        asm_block.prologue(func);
+        asm_block.prologue(ctx, func);
 
         // add inst
-        for ir_block in &*func.ir_blocks.borrow() {
-            CONTEXT_STACK.with(|stack| {
-                let mut stack = stack.borrow_mut();
-                stack.enter_block_scope(Rc::clone(ir_block));
-            });
-
-            for inst in CONTEXT_STACK.with(|stack| {
-                let stack = stack.borrow();
-                stack.get_current_inst_list().borrow().clone()
-            }) {
-                let inst_data = {
-                    let dfg = CONTEXT_STACK.with(|stack| stack.borrow().get_current_dfg());
-                    let dfg_borrow = dfg.borrow();
-                    dfg_borrow.get_inst(&inst).unwrap().clone()
-                };
-
-                let asm_insts = {
-                    AsmInst::from(&inst, &inst_data)
-                };
+        for (i, ir_block) in func.ir_blocks.borrow().iter().enumerate() {
+            let mut insts = vec![];
+            for inst in ir_block.inst_list.borrow().iter() {
+                let inst_data = func.dfg.borrow().get_inst(inst).unwrap().clone();
 
-                asm_block.insts.extend(asm_insts);
+                insts.extend(AsmInst::from(ctx, inst, &inst_data));
             }
 
-            CONTEXT_STACK.with(|stack| {
-                let mut stack = stack.borrow_mut();
-                stack.exit_scope();
-            });
+            // the entry block directly follows the prologue, others are labeled
+            if i == 0 {
+                asm_block.insts.extend(insts);
+                asm_block.insts = legalize(std::mem::take(&mut asm_block.insts));
+            } else {
+                let mut block = AsmBlock::new(asm_label(ctx, &ir_block.label));
+                block.insts = legalize(insts);
+                asm_block.blocks.push(block);
+            }
         }
 
-        STK_FRM_MANAGER.with(|manager| {
-            let mut manager = manager.borrow_mut();
-            manager.epilogue();
-        });
+        ctx.stk_frm_manager.epilogue();
 
         asm_block
     }
@@ -166,11 +178,8 @@ impl AsmBlock {
     /// 2. how much space to allocate for callee-saved registers
     /// 3. how much space to allocate for local variables
     /// 4. whether to allocate space for function calling.
-    pub fn prologue(&mut self, func: &Func) {
-        STK_FRM_MANAGER.with(|manager| {
-            let mut manager = manager.borrow_mut();
-            manager.prologue(func);
-        });
+    pub fn prologue(&mut self, ctx: &mut AsmContext, func: &Func) {
+        ctx.stk_frm_manager.prologue(func, &ctx.funcs);
 
         let mut asm_insts: Vec<AsmInst> = vec![];
 
@@ -180,20 +189,22 @@ impl AsmBlock {
             rd: Some(RegAllocType::Temp(RVRegCode::SP)),
             rs1: Some(RegAllocType::Temp(RVRegCode::SP)),
             rs2: None,
-            imm: Some(-STK_FRM_MANAGER.with(|manager| manager.borrow().get_size() as i32)),
+            imm: Some(-(ctx.stk_frm_manager.get_size() as i32)),
+            label: None,
         });
 
         // store return address
-        if STK_FRM_MANAGER.with(|manager| manager.borrow().is_callee()) {
+        if ctx.stk_frm_manager.is_caller() {
             asm_insts.push(AsmInst {
                 opcode: RVOpCode::SW,
                 rd: None,
                 rs1: Some(RegAllocType::MemWithReg {
                     reg: RVRegCode::SP,
-                    offset: 0, // Default offset value added
+                    offset: ctx.stk_frm_manager.get_ra_offset(),
                 }),
-                rs2: None,
+                rs2: Some(RegAllocType::Temp(RVRegCode::RA)),
                 imm: None,
+                label: None,
             });
         }
 
@@ -209,40 +220,68 @@ pub struct AsmInst {
     pub rs1: Option<RegAllocType>,
     pub rs2: Option<RegAllocType>,
     pub imm: Option<i32>,
+    pub label: Option<String>, // jump target of branch and jump
 }
 
 impl std::fmt::Display for AsmInst {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.opcode)?;
         match self.opcode {
-            RVOpCode::LW => {
+            RVOpCode::LABEL => {
+                write!(f, "{}:", &self.label.as_ref().unwrap())?;
+            }
+
+            RVOpCode::LW | RVOpCode::FLW => {
                 write!(f, " {}, {}", &self.rs1.as_ref().unwrap(), &self.rd.as_ref().unwrap())?;
             }
 
-            RVOpCode::SW => {
+            RVOpCode::SW | RVOpCode::FSW => {
                 write!(f, " {}, {}", &self.rs2.as_ref().unwrap(), &self.rs1.as_ref().unwrap())?;
             }
 
+            // float to int conversion truncates toward zero like C
+            RVOpCode::FCVTWS => {
+                write!(f, " {}, {}, rtz", &self.rd.as_ref().unwrap(), &self.rs1.as_ref().unwrap())?;
+            }
+
+            RVOpCode::BEQZ | RVOpCode::BNEZ => {
+                write!(f, " {}, {}", &self.rs1.as_ref().unwrap(), &self.label.as_ref().unwrap())?;
+            }
+
+            RVOpCode::LA => {
+                write!(f, " {}, {}", &self.rd.as_ref().unwrap(), &self.label.as_ref().unwrap())?;
+            }
+
+            RVOpCode::J | RVOpCode::CALL => {
+                write!(f, " {}", &self.label.as_ref().unwrap())?;
+            }
+
             _ => {
-                if self.rd.is_some() {
-                    write!(f, " {}", &self.rd.as_ref().unwrap())?;
+                if let Some(rd) = &self.rd {
+                    write!(f, " {}", rd)?;
                 }
-                if self.rs1.is_some() {
-                    write!(f, ", {}", &self.rs1.as_ref().unwrap())?;
+                if let Some(rs1) = &self.rs1 {
+                    write!(f, ", {}", rs1)?;
                 }
-                if self.rs2.is_some() {
-                    write!(f, ", {}", &self.rs2.as_ref().unwrap())?;
+                if let Some(rs2) = &self.rs2 {
+                    write!(f, ", {}", rs2)?;
                 }
-                if self.imm.is_some() {
-                    write!(f, ", {}", self.imm.as_ref().unwrap())?;
+                if let Some(imm) = &self.imm {
+                    write!(f, ", {}", imm)?;
                 }
             }
         }
-        writeln!(f, "")?;
+        writeln!(f)?;
         Ok(())
     }
 }
 
+impl Default for AsmInst {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AsmInst {
     pub fn new() -> Self {
         Self {
@@ -251,31 +290,142 @@ impl AsmInst {
             rs1: None,
             rs2: None,
             imm: None,
+            label: None,
         }
     }
 
     pub fn from(
+        ctx: &mut AsmContext,
         inst: &u32,
         inst_data: &InstData,
     ) -> Vec<Self> {
         let mut v = Vec::new();
 
         let reg_used: RegAllocType = match inst_data.opcode {
+            // operands of float operations are both float, for they're converted in koopa ir
+            KoopaOpCode::ADD
+            | KoopaOpCode::SUB
+            | KoopaOpCode::MUL
+            | KoopaOpCode::DIV
+            | KoopaOpCode::EQ
+            | KoopaOpCode::NE
+            | KoopaOpCode::LT
+            | KoopaOpCode::LE
+            | KoopaOpCode::GT
+            | KoopaOpCode::GE if is_float(ctx, inst_data.operands.first().unwrap()) => {
+                let rs1 = process_float_op(ctx, &mut v, inst, inst_data.operands.first().unwrap());
+                let rs2 = process_float_op(ctx, &mut v, inst, inst_data.operands.get(1).unwrap());
+
+                // a > b is b < a, a >= b is b <= a, and a != b is !(a == b)
+                let (rv_opcode, lhs, rhs) = match inst_data.opcode {
+                    KoopaOpCode::ADD => (RVOpCode::FADDS, &rs1, &rs2),
+                    KoopaOpCode::SUB => (RVOpCode::FSUBS, &rs1, &rs2),
+                    KoopaOpCode::MUL => (RVOpCode::FMULS, &rs1, &rs2),
+                    KoopaOpCode::DIV => (RVOpCode::FDIVS, &rs1, &rs2),
+                    KoopaOpCode::EQ | KoopaOpCode::NE => (RVOpCode::FEQS, &rs1, &rs2),
+                    KoopaOpCode::LT => (RVOpCode::FLTS, &rs1, &rs2),
+                    KoopaOpCode::LE => (RVOpCode::FLES, &rs1, &rs2),
+                    KoopaOpCode::GT => (RVOpCode::FLTS, &rs2, &rs1),
+                    KoopaOpCode::GE => (RVOpCode::FLES, &rs2, &rs1),
+                    _ => unreachable!(),
+                };
+
+                // comparisons result in int
+                let rd = match inst_data.typ {
+                    BType::Float => ctx.reg_allocator.find_and_occupy_float_reg(*inst),
+                    _ => ctx.reg_allocator.find_and_occupy_temp_reg(*inst),
+                };
+                v.push(AsmInst {
+                    opcode: rv_opcode,
+                    rd: Some(rd.clone()),
+                    rs1: Some(lhs.clone()),
+                    rs2: Some(rhs.clone()),
+                    imm: None,
+                    label: None,
+                });
+
+                if let KoopaOpCode::NE = inst_data.opcode {
+                    v.push(AsmInst {
+                        opcode: RVOpCode::SEQZ,
+                        rd: Some(rd.clone()),
+                        rs1: Some(rd.clone()),
+                        rs2: None,
+                        imm: None,
+                        label: None,
+                    });
+                }
+
+                rs1.free_temp(&mut ctx.reg_allocator);
+                rs2.free_temp(&mut ctx.reg_allocator);
+                rd.free_temp(&mut ctx.reg_allocator);
+                v.push(AsmInst {
+                    opcode: match rd {
+                        RegAllocType::Float(_) => RVOpCode::FSW,
+                        _ => RVOpCode::SW,
+                    },
+                    rd: None,
+                    rs1: Some(ctx.stk_frm_manager.alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone())),
+                    rs2: Some(rd.clone()),
+                    imm: None,
+                    label: None,
+                });
+                RegAllocType::None
+            }
+
+            KoopaOpCode::SITOFP | KoopaOpCode::FPTOSI => {
+                let (rv_opcode, rs1, rd) = match inst_data.opcode {
+                    KoopaOpCode::SITOFP => (
+                        RVOpCode::FCVTSW,
+                        process_op(ctx, &mut v, inst, inst_data.operands.first().unwrap()),
+                        ctx.reg_allocator.find_and_occupy_float_reg(*inst),
+                    ),
+                    _ => (
+                        RVOpCode::FCVTWS,
+                        process_float_op(ctx, &mut v, inst, inst_data.operands.first().unwrap()),
+                        ctx.reg_allocator.find_and_occupy_temp_reg(*inst),
+                    ),
+                };
+                v.push(AsmInst {
+                    opcode: rv_opcode,
+                    rd: Some(rd.clone()),
+                    rs1: Some(rs1.clone()),
+                    rs2: None,
+                    imm: None,
+                    label: None,
+                });
+
+                rs1.free_temp(&mut ctx.reg_allocator);
+                rd.free_temp(&mut ctx.reg_allocator);
+                v.push(AsmInst {
+                    opcode: match rd {
+                        RegAllocType::Float(_) => RVOpCode::FSW,
+                        _ => RVOpCode::SW,
+                    },
+                    rd: None,
+                    rs1: Some(ctx.stk_frm_manager.alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone())),
+                    rs2: Some(rd.clone()),
+                    imm: None,
+                    label: None,
+                });
+                RegAllocType::None
+            }
+
             KoopaOpCode::EQ | KoopaOpCode::NE => {
-                let rs1 = process_op(&mut v, inst, inst_data.operands.first().unwrap());
-                let rs2 = process_op(&mut v, inst, inst_data.operands.get(1).unwrap());
+                let rs1 = process_op(ctx, &mut v, inst, inst_data.operands.first().unwrap());
+                let rs2 = process_op(ctx, &mut v, inst, inst_data.operands.get(1).unwrap());
 
                 // manually specify the type of anonymous var.
-                let rd1 = RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().find_and_occupy_temp_reg(*inst));
+                let rd1 = ctx.reg_allocator.find_and_occupy_temp_reg(*inst);
                 v.push(AsmInst {
                     opcode: RVOpCode::XOR, // for now we just use xor to represent eq
                     rd: Some(rd1.clone()),
                     rs1: Some(rs1.clone()),
                     rs2: Some(rs2.clone()),
                     imm: None,
+                    label: None,
                 });
 
-                let rd2 = RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().find_and_occupy_temp_reg(*inst));
+                let rd2 = ctx.reg_allocator.find_and_occupy_temp_reg(*inst);
                 v.push(AsmInst {
                     opcode: match inst_data.opcode {
                         KoopaOpCode::EQ => RVOpCode::SEQZ, // set if equal to zero
@@ -286,16 +436,21 @@ impl AsmInst {
                     rs1: Some(rd1.clone()),
                     rs2: None,
                     imm: None,
+                    label: None,
                 });
 
-                rs1.free_temp(); rs2.free_temp(); rd1.free_temp(); rd2.free_temp();
+                rs1.free_temp(&mut ctx.reg_allocator);
+                rs2.free_temp(&mut ctx.reg_allocator);
+                rd1.free_temp(&mut ctx.reg_allocator);
+                rd2.free_temp(&mut ctx.reg_allocator);
                 // save value to mem
                 v.push(AsmInst {
                     opcode: RVOpCode::SW,
                     rd: None,
-                    rs1: Some(STK_FRM_MANAGER.with(|manager| manager.borrow_mut().alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone()))),
+                    rs1: Some(ctx.stk_frm_manager.alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone())),
                     rs2: Some(rd2.clone()),
                     imm: None,
+                    label: None,
                 });
                 // Some(rd2)
                 RegAllocType::None
@@ -303,28 +458,30 @@ impl AsmInst {
 
             KoopaOpCode::AND
             | KoopaOpCode::OR => {
-                let rs1 = process_op(&mut v, inst, inst_data.operands.first().unwrap());
-                let rs2 = process_op(&mut v, inst, inst_data.operands.get(1).unwrap());
+                let rs1 = process_op(ctx, &mut v, inst, inst_data.operands.first().unwrap());
+                let rs2 = process_op(ctx, &mut v, inst, inst_data.operands.get(1).unwrap());
 
-                let rd1 = RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().find_and_occupy_temp_reg(*inst));
+                let rd1 = ctx.reg_allocator.find_and_occupy_temp_reg(*inst);
                 v.push(AsmInst {
                     opcode: RVOpCode::SNEZ,
                     rd: Some(rd1.clone()),
                     rs1: Some(rs1.clone()),
                     rs2: None,
                     imm: None,
+                    label: None,
                 });
 
-                let rd2 = RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().find_and_occupy_temp_reg(*inst));
+                let rd2 = ctx.reg_allocator.find_and_occupy_temp_reg(*inst);
                 v.push(AsmInst {
                     opcode: RVOpCode::SNEZ,
                     rd: Some(rd2.clone()),
                     rs1: Some(rs2.clone()),
                     rs2: None,
                     imm: None,
+                    label: None,
                 });
 
-                let rd = RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().find_and_occupy_temp_reg(*inst));
+                let rd = ctx.reg_allocator.find_and_occupy_temp_reg(*inst);
                 v.push(AsmInst {
                     opcode: match inst_data.opcode {
                         KoopaOpCode::AND => RVOpCode::AND,
@@ -335,15 +492,21 @@ impl AsmInst {
                     rs1: Some(rd1.clone()),
                     rs2: Some(rd2.clone()),
                     imm: None,
+                    label: None,
                 });
 
-                rs1.free_temp(); rs2.free_temp(); rd1.free_temp(); rd2.free_temp(); rd.free_temp();
+                rs1.free_temp(&mut ctx.reg_allocator);
+                rs2.free_temp(&mut ctx.reg_allocator);
+                rd1.free_temp(&mut ctx.reg_allocator);
+                rd2.free_temp(&mut ctx.reg_allocator);
+                rd.free_temp(&mut ctx.reg_allocator);
                 v.push(AsmInst {
                     opcode: RVOpCode::SW,
                     rd: None,
-                    rs1: Some(STK_FRM_MANAGER.with(|manager| manager.borrow_mut().alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone()))),
+                    rs1: Some(ctx.stk_frm_manager.alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone())),
                     rs2: Some(rd.clone()),
                     imm: None,
+                    label: None,
                 });
                 // Some(rd)
                 RegAllocType::None
@@ -367,8 +530,8 @@ impl AsmInst {
             | KoopaOpCode::SAR
             | KoopaOpCode::SHL
             | KoopaOpCode::SHR => {
-                let rs1 = process_op(&mut v, inst, inst_data.operands.first().unwrap());
-                let rs2 = process_op(&mut v, inst, inst_data.operands.get(1).unwrap());
+                let rs1 = process_op(ctx, &mut v, inst, inst_data.operands.first().unwrap());
+                let rs2 = process_op(ctx, &mut v, inst, inst_data.operands.get(1).unwrap());
 
                 let rv_opcode = match inst_data.opcode {
                     KoopaOpCode::ADD => RVOpCode::ADD,
@@ -379,10 +542,11 @@ impl AsmInst {
 
                     KoopaOpCode::XOR => RVOpCode::XOR,
 
+                    // a <= b is !(a > b), a >= b is !(a < b)
                     KoopaOpCode::LT => RVOpCode::SLT,
-                    KoopaOpCode::LE => RVOpCode::SLT,
+                    KoopaOpCode::LE => RVOpCode::SGT,
                     KoopaOpCode::GT => RVOpCode::SGT, 
-                    KoopaOpCode::GE => RVOpCode::SGT, 
+                    KoopaOpCode::GE => RVOpCode::SLT, 
 
                     KoopaOpCode::SAR => RVOpCode::SRA,
                     KoopaOpCode::SHL => RVOpCode::SLL,
@@ -390,22 +554,37 @@ impl AsmInst {
                     _ => unreachable!(),
                 };
 
-                let rd = RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().find_and_occupy_temp_reg(*inst));
+                let rd = ctx.reg_allocator.find_and_occupy_temp_reg(*inst);
                 v.push(AsmInst {
                     opcode: rv_opcode,
                     rd: Some(rd.clone()),
                     rs1: Some(rs1.clone()),
                     rs2: Some(rs2.clone()),
                     imm: None,
+                    label: None,
                 });
 
-                rs1.free_temp(); rs2.free_temp(); rd.free_temp();
+                if let KoopaOpCode::LE | KoopaOpCode::GE = inst_data.opcode {
+                    v.push(AsmInst {
+                        opcode: RVOpCode::SEQZ,
+                        rd: Some(rd.clone()),
+                        rs1: Some(rd.clone()),
+                        rs2: None,
+                        imm: None,
+                        label: None,
+                    });
+                }
+
+                rs1.free_temp(&mut ctx.reg_allocator);
+                rs2.free_temp(&mut ctx.reg_allocator);
+                rd.free_temp(&mut ctx.reg_allocator);
                 v.push(AsmInst {
                     opcode: RVOpCode::SW,
                     rd: None,
-                    rs1: Some(STK_FRM_MANAGER.with(|manager| manager.borrow_mut().alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone()))),
+                    rs1: Some(ctx.stk_frm_manager.alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone())),
                     rs2: Some(rd.clone()),
                     imm: None,
+                    label: None,
                 });
                 // Some(rd)
                 RegAllocType::None
@@ -413,13 +592,13 @@ impl AsmInst {
 
             KoopaOpCode::ALLOC => {
                 // only alloc space for ALLOC inst
-                STK_FRM_MANAGER.with(|manager| manager.borrow_mut().alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone()));
+                ctx.stk_frm_manager.alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone());
                 RegAllocType::None
             }
 
             KoopaOpCode::LOAD => {
-                let rd = process_op(&mut v, inst, inst_data.operands.first().unwrap());
-                let rs1 = RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().find_and_occupy_temp_reg(*inst));
+                let rd = to_mem(process_op(ctx, &mut v, inst, inst_data.operands.first().unwrap()));
+                let rs1 = ctx.reg_allocator.find_and_occupy_temp_reg(*inst);
 
                 v.push(AsmInst {
                     opcode: RVOpCode::LW,
@@ -427,46 +606,244 @@ impl AsmInst {
                     rs1: Some(rs1.clone()),
                     rs2: None,
                     imm: None,
+                    label: None,
                 });
 
-                rs1.free_temp(); rd.free_temp();
+                rs1.free_temp(&mut ctx.reg_allocator);
+                rd.free_temp(&mut ctx.reg_allocator);
                 v.push (AsmInst {
                     opcode: RVOpCode::SW,
                     rd: None,
-                    rs1: Some(STK_FRM_MANAGER.with(|manager| manager.borrow_mut().alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone()))),
+                    rs1: Some(ctx.stk_frm_manager.alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone())),
                     rs2: Some(rs1.clone()),
                     imm: None,
+                    label: None,
                 });
                 RegAllocType::None
             }
 
             KoopaOpCode::STORE => {
-                let rs1 = process_op(&mut v, inst, inst_data.operands.get(1).unwrap());    
-                let rs2 = process_op(&mut v, inst, inst_data.operands.first().unwrap());
+                let rs1 = to_mem(process_op(ctx, &mut v, inst, inst_data.operands.get(1).unwrap()));
+                let rs2 = process_op(ctx, &mut v, inst, inst_data.operands.first().unwrap());
 
                 v.push(AsmInst {
-                    opcode: RVOpCode::SW,
+                    // float params arrive in float regs
+                    opcode: match rs2 {
+                        RegAllocType::Float(_) => RVOpCode::FSW,
+                        _ => RVOpCode::SW,
+                    },
                     rd: None,
                     rs1: Some(rs1.clone()),
                     rs2: Some(rs2.clone()),
                     imm: None,
+                    label: None,
                 });
 
-                rs1.free_temp(); rs2.free_temp();
+                rs1.free_temp(&mut ctx.reg_allocator);
+                rs2.free_temp(&mut ctx.reg_allocator);
+                RegAllocType::None
+            }
+
+            KoopaOpCode::GETELEMPTR | KoopaOpCode::GETPTR => {
+                // both of them compute base + index * sizeof(elem)
+                let base = load_addr_to_reg(ctx, &mut v, inst, inst_data.operands.first().unwrap());
+                let index = process_op(ctx, &mut v, inst, inst_data.operands.get(1).unwrap());
+                let elem_size = inst_data.typ.get_elem().size_in_bytes() as i32;
+
+                let offset = ctx.reg_allocator.find_and_occupy_temp_reg(*inst);
+                v.push(AsmInst {
+                    opcode: RVOpCode::LI,
+                    rd: Some(offset.clone()),
+                    rs1: None,
+                    rs2: None,
+                    imm: Some(elem_size),
+                    label: None,
+                });
+                v.push(AsmInst {
+                    opcode: RVOpCode::MUL,
+                    rd: Some(offset.clone()),
+                    rs1: Some(index.clone()),
+                    rs2: Some(offset.clone()),
+                    imm: None,
+                    label: None,
+                });
+                v.push(AsmInst {
+                    opcode: RVOpCode::ADD,
+                    rd: Some(base.clone()),
+                    rs1: Some(base.clone()),
+                    rs2: Some(offset.clone()),
+                    imm: None,
+                    label: None,
+                });
+                v.push(AsmInst {
+                    opcode: RVOpCode::SW,
+                    rd: None,
+                    rs1: Some(ctx.stk_frm_manager.alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone())),
+                    rs2: Some(base.clone()),
+                    imm: None,
+                    label: None,
+                });
+
+                base.free_temp(&mut ctx.reg_allocator);
+                index.free_temp(&mut ctx.reg_allocator);
+                offset.free_temp(&mut ctx.reg_allocator);
+                RegAllocType::None
+            }
+
+            KoopaOpCode::BR => {
+                let cond = process_op(ctx, &mut v, inst, inst_data.operands.first().unwrap());
+
+                // conditional branches only reach 4 KiB away, so they skip over jumps
+                // to the targets, which reach 1 MiB away
+                let skip_label = format!(".L{}_br{}", ctx.get_current_func().name, inst);
+                v.push(AsmInst {
+                    opcode: RVOpCode::BEQZ,
+                    rd: None,
+                    rs1: Some(cond.clone()),
+                    rs2: None,
+                    imm: None,
+                    label: Some(skip_label.clone()),
+                });
+
+                v.push(AsmInst {
+                    opcode: RVOpCode::J,
+                    rd: None,
+                    rs1: None,
+                    rs2: None,
+                    imm: None,
+                    label: Some(asm_label(ctx, &inst_data.operands[1].get_label())),
+                });
+
+                v.push(AsmInst {
+                    opcode: RVOpCode::LABEL,
+                    rd: None,
+                    rs1: None,
+                    rs2: None,
+                    imm: None,
+                    label: Some(skip_label),
+                });
+
+                v.push(AsmInst {
+                    opcode: RVOpCode::J,
+                    rd: None,
+                    rs1: None,
+                    rs2: None,
+                    imm: None,
+                    label: Some(asm_label(ctx, &inst_data.operands[2].get_label())),
+                });
+
+                cond.free_temp(&mut ctx.reg_allocator);
+                RegAllocType::None
+            }
+
+            KoopaOpCode::JUMP => {
+                v.push(AsmInst {
+                    opcode: RVOpCode::J,
+                    rd: None,
+                    rs1: None,
+                    rs2: None,
+                    imm: None,
+                    label: Some(asm_label(ctx, &inst_data.operands[0].get_label())),
+                });
+                RegAllocType::None
+            }
+
+            KoopaOpCode::CALL => {
+                let args = &inst_data.operands[1..];
+                let callee = ctx.get_func(&inst_data.operands[0].get_func());
+                let locs = arg_locations(&callee.params);
+
+                // arguments on stack are stored to the bottom of the frame first,
+                // then the float ones, for a0-a7 may be used as temp regs when loading them.
+                for (arg, loc) in args.iter().zip(&locs) {
+                    if let ArgLoc::Stack(offset) = loc {
+                        let rs2 = process_op(ctx, &mut v, inst, arg);
+                        v.push(AsmInst {
+                            opcode: RVOpCode::SW,
+                            rd: None,
+                            rs1: Some(RegAllocType::MemWithReg {
+                                reg: RVRegCode::SP,
+                                offset: *offset,
+                            }),
+                            rs2: Some(rs2.clone()),
+                            imm: None,
+                            label: None,
+                        });
+                        rs2.free_temp(&mut ctx.reg_allocator);
+                    }
+                }
+
+                for (arg, loc) in args.iter().zip(&locs) {
+                    if let ArgLoc::FloatReg(reg) = loc {
+                        load_op_to_freg(ctx, &mut v, inst, arg, *reg);
+                    }
+                }
+
+                for (arg, loc) in args.iter().zip(&locs) {
+                    if let ArgLoc::Reg(reg) = loc {
+                        load_op_to_reg(ctx, &mut v, arg, *reg);
+                    }
+                }
+
+                v.push(AsmInst {
+                    opcode: RVOpCode::CALL,
+                    rd: None,
+                    rs1: None,
+                    rs2: None,
+                    imm: None,
+                    label: Some(inst_data.operands[0].get_func()),
+                });
+
+                // the return value is in a0, or fa0 if it's float
+                if let IRObj::InstId(_) = inst_data.ir_obj {
+                    let (opcode, rs2) = match inst_data.typ {
+                        BType::Float => (RVOpCode::FSW, RegAllocType::Float(RVFRegCode::FA0)),
+                        _ => (RVOpCode::SW, RegAllocType::Temp(RVRegCode::A0)),
+                    };
+                    v.push(AsmInst {
+                        opcode,
+                        rd: None,
+                        rs1: Some(ctx.stk_frm_manager.alloc_named_var_wrapped(inst_data.ir_obj.to_string(), inst_data.typ.clone())),
+                        rs2: Some(rs2),
+                        imm: None,
+                        label: None,
+                    });
+                }
                 RegAllocType::None
             }
 
             KoopaOpCode::RET => {
                 // if we need to load imm at return point, we must use a0 anyway.
-                let op_reg = process_op(&mut v, inst, inst_data.operands.first().unwrap());
+                let operand = inst_data.operands.first().unwrap();
+                let op_reg = if is_float(ctx, operand) {
+                    load_op_to_freg(ctx, &mut v, inst, operand, RVFRegCode::FA0);
+                    RegAllocType::None
+                } else {
+                    process_op(ctx, &mut v, inst, operand)
+                };
 
                 // epilogue here
+                if ctx.stk_frm_manager.is_caller() {
+                    v.push(AsmInst {
+                        opcode: RVOpCode::LW,
+                        rd: Some(RegAllocType::MemWithReg {
+                            reg: RVRegCode::SP,
+                            offset: ctx.stk_frm_manager.get_ra_offset(),
+                        }),
+                        rs1: Some(RegAllocType::Temp(RVRegCode::RA)),
+                        rs2: None,
+                        imm: None,
+                        label: None,
+                    });
+                }
+
                 v.push(AsmInst {
                     opcode: RVOpCode::ADDI,
                     rd: Some(RegAllocType::Temp(RVRegCode::SP)),
                     rs1: Some(RegAllocType::Temp(RVRegCode::SP)),
                     rs2: None,
-                    imm: Some(STK_FRM_MANAGER.with(|manager| manager.borrow().get_size() as i32)),
+                    imm: Some(ctx.stk_frm_manager.get_size() as i32),
+                    label: None,
                 });
 
                 v.push(AsmInst {
@@ -475,49 +852,50 @@ impl AsmInst {
                     rs1: None,
                     rs2: None,
                     imm: None,
+                    label: None,
                 });
 
-                op_reg.free_temp();
+                op_reg.free_temp(&mut ctx.reg_allocator);
                 RegAllocType::None
             }
         };
 
         // only permanently allocated regs are recorded in DFG
         if let RegAllocType::Perm(reg) = reg_used {
-            CONTEXT_STACK.with(|stack| {
-                let stack = stack.borrow();
-                stack.get_current_dfg().borrow_mut().set_reg(inst, Some(reg));
-            });
+            let func = ctx.get_current_func();
+            func.dfg.borrow_mut().set_reg(inst, Some(reg), &mut ctx.reg_allocator);
         }
         v
     }
 }
 
 fn process_op(
+    ctx: &mut AsmContext,
     v: &mut Vec<AsmInst>,
     current_inst_id: &u32,
     operand: &Operand,
 ) -> RegAllocType {
-    let opcode = CONTEXT_STACK.with(|stack| 
-        stack
-        .borrow()
+    let opcode = ctx
         .get_current_dfg()
-        .borrow()
         .get_inst(current_inst_id)
         .unwrap()
         .opcode
-        .clone());
+        .clone();
 
     match operand {
+        // floats are loaded as their bits
+        Operand::FloatConst(val) => process_op(ctx, v, current_inst_id, &Operand::Const(val.to_bits() as i32)),
+
         Operand::Const(val) => {
-            if *val == 0 {
+            // the returned value must be in a0 even if it's 0
+            if *val == 0 && !matches!(opcode, KoopaOpCode::RET) {
                 return RegAllocType::Temp(RVRegCode::ZERO);
             }
 
             // find a free temp reg
             if let RegAllocType::Temp(temp_reg) = match opcode {
                 KoopaOpCode::RET => RegAllocType::Temp(RVRegCode::A0), // for return, we must use a0
-                _ => RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().find_and_occupy_temp_reg(*current_inst_id))
+                _ => ctx.reg_allocator.find_and_occupy_temp_reg(*current_inst_id)
             } {
                 // load imm into the free reg
                 let asm_inst = AsmInst {
@@ -526,6 +904,7 @@ fn process_op(
                     rs1: None,
                     rs2: None,
                     imm: Some(*val),
+                    label: None,
                 };
 
                 v.push(asm_inst);
@@ -536,10 +915,10 @@ fn process_op(
         }
 
         Operand::InstId(inst_id) => {
-            let mem_with_reg = STK_FRM_MANAGER.with(|manager| manager.borrow().get_named_var_wrapped(Operand::InstId(*inst_id).to_string()));
+            let mem_with_reg = ctx.stk_frm_manager.get_named_var_wrapped(Operand::InstId(*inst_id).to_string());
             let rs1 = match opcode {
                 KoopaOpCode::RET => RegAllocType::Temp(RVRegCode::A0), // for return, we must use a0
-                _ => RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().find_and_occupy_temp_reg(*inst_id))
+                _ => ctx.reg_allocator.find_and_occupy_temp_reg(*inst_id)
             };
 
             v.push(AsmInst {
@@ -548,19 +927,240 @@ fn process_op(
                 rs1: Some(rs1.clone()),
                 rs2: None,
                 imm: None,
+                label: None,
             });
 
             rs1
         }
 
-        Operand::Pointer(pointer_id) => {
-            STK_FRM_MANAGER.with(|manager| manager.borrow().get_named_var_wrapped(Operand::Pointer(*pointer_id).to_string()))
+        Operand::Pointer(name) => {
+            ctx.stk_frm_manager.get_named_var_wrapped(Operand::Pointer(name.clone()).to_string())
         }
 
-        Operand::BType(_) => {
-            unreachable!()  // b_type as a operand would never reach here.
+        Operand::GlobalPointer(name) => {
+            // globals live in the data section, so address them through their symbol
+            let rs1 = ctx.reg_allocator.find_and_occupy_temp_reg(*current_inst_id);
+            v.push(AsmInst {
+                opcode: RVOpCode::LA,
+                rd: Some(rs1.clone()),
+                rs1: None,
+                rs2: None,
+                imm: None,
+                label: Some(name.clone()),
+            });
+            RegAllocType::MemWithReg {
+                offset: 0,
+                reg: rs1.get_reg(),
+            }
+        }
+
+        Operand::Param(name) => {
+            let func = ctx.get_current_func();
+            let index = func.params.iter().position(|param| param.name == *name).unwrap();
+
+            match arg_locations(&func.params)[index] {
+                ArgLoc::Reg(reg) => RegAllocType::Temp(reg),
+                ArgLoc::FloatReg(reg) => RegAllocType::Float(reg),
+                // params out of registers lie at the bottom of caller's frame
+                ArgLoc::Stack(offset) => {
+                    let rs1 = ctx.reg_allocator.find_and_occupy_temp_reg(*current_inst_id);
+                    v.push(AsmInst {
+                        opcode: RVOpCode::LW,
+                        rd: Some(RegAllocType::MemWithReg {
+                            reg: RVRegCode::SP,
+                            offset: ctx.stk_frm_manager.get_size() + offset,
+                        }),
+                        rs1: Some(rs1.clone()),
+                        rs2: None,
+                        imm: None,
+                        label: None,
+                    });
+                    rs1
+                }
+            }
+        }
+
+        Operand::BType(_) | Operand::Label(_) | Operand::Func(_) => {
+            unreachable!()  // b_type, label and func as a operand would never reach here.
         }
 
         Operand::None => RegAllocType::None,
     }
 }
+
+/// offsets and immediates beyond 12 bits are materialized in the reserved t6 first.
+fn legalize(insts: Vec<AsmInst>) -> Vec<AsmInst> {
+    let is_imm12 = |imm: i32| (IMM12_MIN..=IMM12_MAX).contains(&imm);
+    let scratch = RegAllocType::Temp(RVRegCode::T6);
+    let li_scratch = |imm: i32| AsmInst {
+        opcode: RVOpCode::LI,
+        rd: Some(scratch.clone()),
+        rs1: None,
+        rs2: None,
+        imm: Some(imm),
+        label: None,
+    };
+    // t6 = reg + offset, then the memory operand becomes 0(t6)
+    let add_scratch = |reg: RVRegCode| AsmInst {
+        opcode: RVOpCode::ADD,
+        rd: Some(scratch.clone()),
+        rs1: Some(scratch.clone()),
+        rs2: Some(RegAllocType::Temp(reg)),
+        imm: None,
+        label: None,
+    };
+    let scratch_mem = RegAllocType::MemWithReg {
+        offset: 0,
+        reg: RVRegCode::T6,
+    };
+
+    let mut v = Vec::with_capacity(insts.len());
+    for mut inst in insts {
+        match (&inst.opcode, &inst.rd, &inst.rs1, inst.imm) {
+            (RVOpCode::LW | RVOpCode::FLW, Some(RegAllocType::MemWithReg { offset, reg }), _, _)
+            | (RVOpCode::SW | RVOpCode::FSW, _, Some(RegAllocType::MemWithReg { offset, reg }), _)
+                if !is_imm12(*offset as i32) =>
+            {
+                v.push(li_scratch(*offset as i32));
+                v.push(add_scratch(*reg));
+                match inst.opcode {
+                    RVOpCode::LW | RVOpCode::FLW => inst.rd = Some(scratch_mem.clone()),
+                    _ => inst.rs1 = Some(scratch_mem.clone()),
+                }
+                v.push(inst);
+            }
+
+            (RVOpCode::ADDI, _, _, Some(imm)) if !is_imm12(imm) => {
+                v.push(li_scratch(imm));
+                v.push(AsmInst {
+                    opcode: RVOpCode::ADD,
+                    rd: inst.rd,
+                    rs1: inst.rs1,
+                    rs2: Some(scratch.clone()),
+                    imm: None,
+                    label: None,
+                });
+            }
+
+            _ => v.push(inst),
+        }
+    }
+    v
+}
+
+/// pointers computed by getelemptr/getptr hold the address themselves.
+fn to_mem(reg: RegAllocType) -> RegAllocType {
+    match reg {
+        RegAllocType::Temp(reg) => RegAllocType::MemWithReg { offset: 0, reg },
+        _ => reg,
+    }
+}
+
+/// load the address that a pointer operand refers to into a temp reg.
+fn load_addr_to_reg(ctx: &mut AsmContext, v: &mut Vec<AsmInst>, current_inst_id: &u32, operand: &Operand) -> RegAllocType {
+    match process_op(ctx, v, current_inst_id, operand) {
+        // local variable in stack frame
+        RegAllocType::MemWithReg {
+            offset,
+            reg: RVRegCode::SP,
+        } => {
+            let rd = ctx.reg_allocator.find_and_occupy_temp_reg(*current_inst_id);
+            v.push(AsmInst {
+                opcode: RVOpCode::ADDI,
+                rd: Some(rd.clone()),
+                rs1: Some(RegAllocType::Temp(RVRegCode::SP)),
+                rs2: None,
+                imm: Some(offset as i32),
+                label: None,
+            });
+            rd
+        }
+        // global variable, whose address has been loaded by la
+        RegAllocType::MemWithReg { reg, .. } => RegAllocType::Temp(reg),
+        reg => reg,
+    }
+}
+
+/// load operand into the given reg, for the calling convention decides which reg to use.
+fn load_op_to_reg(ctx: &AsmContext, v: &mut Vec<AsmInst>, operand: &Operand, reg: RVRegCode) {
+    match operand {
+        Operand::Const(val) => {
+            v.push(AsmInst {
+                opcode: RVOpCode::LI,
+                rd: Some(RegAllocType::Temp(reg)),
+                rs1: None,
+                rs2: None,
+                imm: Some(*val),
+                label: None,
+            });
+        }
+
+        Operand::FloatConst(val) => load_op_to_reg(ctx, v, &Operand::Const(val.to_bits() as i32), reg),
+
+        Operand::InstId(inst_id) => {
+            v.push(AsmInst {
+                opcode: RVOpCode::LW,
+                rd: Some(ctx.stk_frm_manager.get_named_var_wrapped(Operand::InstId(*inst_id).to_string())),
+                rs1: Some(RegAllocType::Temp(reg)),
+                rs2: None,
+                imm: None,
+                label: None,
+            });
+        }
+
+        _ => unreachable!(), // arguments are always values
+    }
+}
+
+/// load a float operand into the given float reg.
+fn load_op_to_freg(ctx: &mut AsmContext, v: &mut Vec<AsmInst>, current_inst_id: &u32, operand: &Operand, reg: RVFRegCode) {
+    match operand {
+        Operand::InstId(inst_id) => {
+            v.push(AsmInst {
+                opcode: RVOpCode::FLW,
+                rd: Some(ctx.stk_frm_manager.get_named_var_wrapped(Operand::InstId(*inst_id).to_string())),
+                rs1: Some(RegAllocType::Float(reg)),
+                rs2: None,
+                imm: None,
+                label: None,
+            });
+        }
+
+        // there is no float immediate, so constants are moved from an int reg
+        _ => {
+            let rs1 = process_op(ctx, v, current_inst_id, operand);
+            v.push(AsmInst {
+                opcode: RVOpCode::FMVWX,
+                rd: Some(RegAllocType::Float(reg)),
+                rs1: Some(rs1.clone()),
+                rs2: None,
+                imm: None,
+                label: None,
+            });
+            rs1.free_temp(&mut ctx.reg_allocator);
+        }
+    }
+}
+
+/// load a float operand into a free float temp reg.
+fn process_float_op(ctx: &mut AsmContext, v: &mut Vec<AsmInst>, current_inst_id: &u32, operand: &Operand) -> RegAllocType {
+    let rd = ctx.reg_allocator.find_and_occupy_float_reg(*current_inst_id);
+    load_op_to_freg(ctx, v, current_inst_id, operand, rd.get_freg());
+    rd
+}
+
+/// whether the operand is a float value, e.g. a float constant or the result of fadd.
+fn is_float(ctx: &AsmContext, operand: &Operand) -> bool {
+    match operand {
+        Operand::FloatConst(_) => true,
+        Operand::InstId(inst_id) => ctx.get_current_dfg().get_inst(inst_id).unwrap().typ == BType::Float,
+        _ => false,
+    }
+}
+
+/// labels of koopa blocks are local to a function, so they become local asm labels
+/// numbered in the function, e.g. `.Lmain_1`, which can't clash with global symbols.
+fn asm_label(ctx: &AsmContext, ir_label: &str) -> String {
+    let func = ctx.get_current_func();
+    format!(".L{}_{}", func.name, ctx.labels[ir_label])
+}
@@ -1,12 +1,16 @@
 use crate::config::config::BType;
-use crate::koopa_ir::koopa_ir::Func;
+use crate::koopa_ir::config::KoopaOpCode;
+use crate::koopa_ir::koopa_ir::{DataFlowGraph, Func, Param, Program};
 
-use std::cell::RefCell;
+use std::cell::Ref;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 const STK_FRM_BASE_LENGTH: u32 = 16; // 16 bytes for minimum
-const RISCV_BITS: u32 = 32;
+pub const ARG_REG_NUM: usize = 8; // a0-a7 pass the first 8 arguments
 const REG_IDLE: u32 = u32::MAX;
+pub const IMM12_MIN: i32 = -2048; // range of 12-bit signed immediates
+pub const IMM12_MAX: i32 = 2047;
 
 #[derive(Clone, Debug)]
 pub enum RVOpCode {
@@ -39,6 +43,21 @@ pub enum RVOpCode {
     LI,
     LA,
     MV,
+    // single-precision instructions of the F extension
+    FLW,
+    FSW,
+    FADDS,
+    FSUBS,
+    FMULS,
+    FDIVS,
+    FEQS,
+    FLTS,
+    FLES,
+    FCVTSW,
+    FCVTWS,
+    FMVWX,
+    // not an instruction, a label within a block that the label field names
+    LABEL,
 }
 
 impl std::fmt::Display for RVOpCode {
@@ -73,6 +92,19 @@ impl std::fmt::Display for RVOpCode {
             RVOpCode::LI => write!(f, "li"),
             RVOpCode::LA => write!(f, "la"),
             RVOpCode::MV => write!(f, "mv"),
+            RVOpCode::FLW => write!(f, "flw"),
+            RVOpCode::FSW => write!(f, "fsw"),
+            RVOpCode::FADDS => write!(f, "fadd.s"),
+            RVOpCode::FSUBS => write!(f, "fsub.s"),
+            RVOpCode::FMULS => write!(f, "fmul.s"),
+            RVOpCode::FDIVS => write!(f, "fdiv.s"),
+            RVOpCode::FEQS => write!(f, "feq.s"),
+            RVOpCode::FLTS => write!(f, "flt.s"),
+            RVOpCode::FLES => write!(f, "fle.s"),
+            RVOpCode::FCVTSW => write!(f, "fcvt.s.w"),
+            RVOpCode::FCVTWS => write!(f, "fcvt.w.s"),
+            RVOpCode::FMVWX => write!(f, "fmv.w.x"),
+            RVOpCode::LABEL => Ok(()),
         }
     }
 }
@@ -114,12 +146,92 @@ pub enum RVRegCode {
     T6 = 31, // temporaries
 }
 
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RVFRegCode {
+    FT0 = 0,
+    FT1 = 1,
+    FT2 = 2,
+    FT3 = 3,
+    FT4 = 4,
+    FT5 = 5,
+    FT6 = 6,
+    FT7 = 7, // temporaries
+    FS0 = 8,
+    FS1 = 9, // saved registers
+    FA0 = 10,
+    FA1 = 11,
+    FA2 = 12,
+    FA3 = 13,
+    FA4 = 14,
+    FA5 = 15,
+    FA6 = 16,
+    FA7 = 17, // float arguments / return values
+    FS2 = 18,
+    FS3 = 19,
+    FS4 = 20,
+    FS5 = 21,
+    FS6 = 22,
+    FS7 = 23,
+    FS8 = 24,
+    FS9 = 25,
+    FS10 = 26,
+    FS11 = 27, // saved registers
+    FT8 = 28,
+    FT9 = 29,
+    FT10 = 30,
+    FT11 = 31, // temporaries
+}
+
+impl RVFRegCode {
+    pub fn from_idx(idx: usize) -> RVFRegCode {
+        assert!(idx < 32);
+        unsafe { std::mem::transmute::<u8, RVFRegCode>(idx as u8) }
+    }
+}
+
+impl std::fmt::Display for RVFRegCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "f{}", *self as u8)
+    }
+}
+
+/// where an argument is passed, following the ilp32f calling convention
+#[derive(Clone, Copy, Debug)]
+pub enum ArgLoc {
+    Reg(RVRegCode),
+    FloatReg(RVFRegCode),
+    Stack(u32), // offset from sp of the caller
+}
+
+/// floats are passed in fa0-fa7 and the others in a0-a7,
+/// once the registers run out, floats are passed like ints and the rest go to stack.
+pub fn arg_locations(params: &[Param]) -> Vec<ArgLoc> {
+    let (mut int_num, mut float_num, mut stack_size) = (0, 0, 0);
+    params
+        .iter()
+        .map(|param| {
+            if param.param_type == BType::Float && float_num < ARG_REG_NUM {
+                float_num += 1;
+                ArgLoc::FloatReg(RVFRegCode::from_idx(RVFRegCode::FA0 as usize + float_num - 1))
+            } else if int_num < ARG_REG_NUM {
+                int_num += 1;
+                ArgLoc::Reg(RVRegAllocator::from_idx(RVRegCode::A0 as usize + int_num - 1))
+            } else {
+                stack_size += 4;
+                ArgLoc::Stack(stack_size - 4)
+            }
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub enum RegAllocType {
     Temp(RVRegCode), // reg temporarily allocated, often for asms transformed from the same IR.
     Perm(RVRegCode), // reg permanently allocated, often for the reg eventually used by the whole IR.
 
     MemWithReg { offset: u32, reg: RVRegCode }, // memory location in stack frame
+    Float(RVFRegCode), // float reg temporarily allocated
     None,                                       // no reg allocated
 }
 
@@ -131,6 +243,7 @@ impl std::fmt::Display for RegAllocType {
             RegAllocType::MemWithReg { offset, reg } => {
                 write!(f, "{}({})", offset, reg)
             }
+            RegAllocType::Float(reg) => write!(f, "{}", reg),
             RegAllocType::None => write!(f, ""),
         }
     }
@@ -142,10 +255,18 @@ impl RegAllocType {
             RegAllocType::Temp(reg) => *reg,
             RegAllocType::Perm(reg) => *reg,
             RegAllocType::MemWithReg { reg, .. } => *reg,
+            RegAllocType::Float(_) => panic!("Not an integer register!"),
             RegAllocType::None => panic!("No register allocated!"),
         }
     }
 
+    pub fn get_freg(&self) -> RVFRegCode {
+        match self {
+            RegAllocType::Float(reg) => *reg,
+            _ => panic!("Not a float register!"),
+        }
+    }
+
     pub fn get_offset(&self) -> u32 {
         match self {
             RegAllocType::MemWithReg { offset, .. } => *offset,
@@ -154,21 +275,39 @@ impl RegAllocType {
     }
 
     /// this function would only free temporary registers.
-    pub fn free_temp(&self) {
-        if let RegAllocType::Temp(reg) = self {
-            RVREG_ALLOCATOR.with(|allocator| allocator.borrow_mut().free_reg(*reg));
+    pub fn free_temp(&self, allocator: &mut RVRegAllocator) {
+        match self {
+            RegAllocType::Temp(reg) => {
+                allocator.free_reg(*reg);
+            }
+            // address of a global is held by a temp reg rather than sp
+            RegAllocType::MemWithReg { reg, .. } if *reg != RVRegCode::SP => {
+                allocator.free_reg(*reg);
+            }
+            RegAllocType::Float(reg) => {
+                allocator.free_float_reg(*reg);
+            }
+            _ => {}
         }
     }
 }
 
 pub struct RVRegAllocator {
     pub map: [u32; 32], // each item means the inst id that occupies this register.
+    pub float_map: [u32; 32], // the same for float registers
+}
+
+impl Default for RVRegAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RVRegAllocator {
     pub fn new() -> Self {
         Self {
             map: [REG_IDLE; 32],
+            float_map: [REG_IDLE; 32],
         }
     }
 
@@ -176,15 +315,15 @@ impl RVRegAllocator {
         self.map[reg as usize]
     }
 
-    /// temp regs: t0-t6, a0-a7
+    /// temp regs: t0-t5, a0-a7, t6 is reserved for large offsets
     pub fn find_free_reg(&self) -> Option<RVRegCode> {
         for (i, &inst_id) in self.map.iter().enumerate() {
             if inst_id == REG_IDLE
                 && (i >= RVRegCode::T0 as usize && i <= RVRegCode::T2 as usize
-                    || i >= RVRegCode::T3 as usize && i <= RVRegCode::T6 as usize
+                    || i >= RVRegCode::T3 as usize && i <= RVRegCode::T5 as usize
                     || i >= RVRegCode::A0 as usize && i <= RVRegCode::A7 as usize)
             {
-                return Some(unsafe { std::mem::transmute(i as u8) });
+                return Some(unsafe { std::mem::transmute::<u8, RVRegCode>(i as u8) });
             }
         }
         // TODO: maybe we need to have mem allocation strategy in the future
@@ -217,6 +356,23 @@ impl RVRegAllocator {
         self.map[reg as usize] = REG_IDLE;
     }
 
+    /// float temp regs: ft0-ft7
+    pub fn find_and_occupy_float_reg(&mut self, inst_id: u32) -> RegAllocType {
+        match (RVFRegCode::FT0 as usize..=RVFRegCode::FT7 as usize)
+            .find(|&i| self.float_map[i] == REG_IDLE)
+        {
+            Some(i) => {
+                self.float_map[i] = inst_id;
+                RegAllocType::Float(RVFRegCode::from_idx(i))
+            }
+            None => RegAllocType::None,
+        }
+    }
+
+    pub fn free_float_reg(&mut self, reg: RVFRegCode) {
+        self.float_map[reg as usize] = REG_IDLE;
+    }
+
     pub fn from_idx(idx: usize) -> RVRegCode {
         assert!(idx < 32);
         unsafe { std::mem::transmute::<u8, RVRegCode>(idx as u8) }
@@ -236,6 +392,8 @@ pub struct StackFrame {
     cur_offset: u32,
     // variable map in this stack frame
     var_map: HashMap<String, (u32, u32)>, // variable name to (offset, size) in stack frame
+    // non-leaf function saves ra at the top of its frame
+    is_caller: bool,
 }
 
 #[derive(Debug)]
@@ -243,12 +401,18 @@ pub struct StackFrameManager {
     frames: Vec<StackFrame>,
 }
 
+impl Default for StackFrameManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl StackFrameManager {
     pub fn new() -> Self {
         Self { frames: vec![] }
     }
 
-    pub fn prologue(&mut self, func: &Func) {
+    pub fn prologue(&mut self, func: &Func, funcs: &HashMap<String, Rc<Func>>) {
         // iterate inst_map, calculate stack frame size. as koopa ir is in SSA form,
         // each inst is only assigned once, so we can simply sum up the size of all as long as the inst have return value.
         let dfg = func.dfg.borrow();
@@ -257,10 +421,29 @@ impl StackFrameManager {
             .iter()
             .fold(0, |acc, (_, inst)| acc + inst.typ.size_in_bytes());
 
+        // arguments out of registers are passed at the bottom of caller's frame
+        let calls = dfg
+            .inst_map
+            .values()
+            .filter(|inst| matches!(inst.opcode, KoopaOpCode::CALL));
+        let is_caller = calls.clone().next().is_some();
+        let args_size = calls
+            .map(|inst| {
+                let callee = &funcs[&inst.operands[0].get_func()];
+                arg_locations(&callee.params)
+                    .iter()
+                    .filter(|loc| matches!(loc, ArgLoc::Stack(_)))
+                    .count() as u32
+                    * 4
+            })
+            .max()
+            .unwrap_or(0);
+        let ra_size = if is_caller { 4 } else { 0 };
+
         // actually manager doesn't know the initial value of sp and fp,
         // so we use positive offset to represent them for convenience.
-        let size =
-            ((origin_size + (STK_FRM_BASE_LENGTH - 1)) / STK_FRM_BASE_LENGTH) * STK_FRM_BASE_LENGTH;
+        let size = (args_size + origin_size + ra_size).div_ceil(STK_FRM_BASE_LENGTH)
+            * STK_FRM_BASE_LENGTH;
 
         let (sp_offset, fp_offset) = if let Some(stack_frame) = self.frames.last() {
             (stack_frame.sp_offset + size, stack_frame.sp_offset)
@@ -272,8 +455,9 @@ impl StackFrameManager {
             sp_offset,
             fp_offset,
             size,
-            cur_offset: 0,
+            cur_offset: args_size,
             var_map: HashMap::new(),
+            is_caller,
         });
     }
 
@@ -293,8 +477,13 @@ impl StackFrameManager {
         self.frames.pop();
     }
 
-    pub fn is_callee(&self) -> bool {
-        self.frames.len() > 1
+    pub fn is_caller(&self) -> bool {
+        self.frames.last().is_some_and(|frame| frame.is_caller)
+    }
+
+    /// ra is saved at the top of the frame
+    pub fn get_ra_offset(&self) -> u32 {
+        self.get_size() - 4
     }
 
     // @return (offset, size)
@@ -302,10 +491,7 @@ impl StackFrameManager {
         let frame = self.frames.last_mut().unwrap();
 
         let offset = frame.cur_offset;
-        let size = match typ {
-            BType::Int => 4,
-            BType::Void => 0,
-        };
+        let size = typ.size_in_bytes();
 
         frame.var_map.insert(name, (offset, size));
         frame.cur_offset += size;
@@ -342,12 +528,57 @@ impl StackFrameManager {
     }
 }
 
-thread_local! {
-    /// this array records the occupation status of each register.
-    /// each item means the inst id that occupies this register.
+/// state of generating asm for a program, passed through the codegen of each instruction
+pub struct AsmContext {
+    pub reg_allocator: RVRegAllocator,
+    pub stk_frm_manager: StackFrameManager,
+    // functions defined or declared by the program, for the calling convention of callees
+    pub funcs: HashMap<String, Rc<Func>>,
+    // function being generated
+    pub func: Option<Rc<Func>>,
+    // position of each block in the function being generated, which numbers its label
+    pub labels: HashMap<String, usize>,
+}
 
-    pub static RVREG_ALLOCATOR: RefCell<RVRegAllocator> = RefCell::new(RVRegAllocator::new());
-    pub static STK_FRM_MANAGER: RefCell<StackFrameManager> = RefCell::new(StackFrameManager::new());
+impl AsmContext {
+    pub fn new(program: &Program) -> Self {
+        let funcs = program
+            .func_decls
+            .iter()
+            .chain(&program.funcs)
+            .map(|func| (func.name.clone(), Rc::clone(func)))
+            .collect();
+        AsmContext {
+            reg_allocator: RVRegAllocator::new(),
+            stk_frm_manager: StackFrameManager::new(),
+            funcs,
+            func: None,
+            labels: HashMap::new(),
+        }
+    }
+
+    pub fn enter_func(&mut self, func: &Rc<Func>) {
+        self.labels = func
+            .ir_blocks
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (block.label.clone(), i))
+            .collect();
+        self.func = Some(Rc::clone(func));
+    }
+
+    pub fn get_current_func(&self) -> Rc<Func> {
+        Rc::clone(self.func.as_ref().expect("no function is being generated"))
+    }
+
+    pub fn get_current_dfg(&self) -> Ref<'_, DataFlowGraph> {
+        self.func.as_ref().expect("no function is being generated").dfg.borrow()
+    }
+
+    pub fn get_func(&self, name: &str) -> Rc<Func> {
+        Rc::clone(&self.funcs[name])
+    }
 }
 
 impl std::fmt::Display for RVRegCode {
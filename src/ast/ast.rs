@@ -1,55 +1,185 @@
-use crate::ast::decl::Decl;
+use crate::ast::const_eval::ConstValue;
+use crate::ast::decl::{ConstExp, Decl};
 use crate::ast::stmt::{Statement, Stmt};
-use crate::config::config::{BType, CONTEXT_STACK};
-use crate::koopa_ir::koopa_ir::{Func, IRBlock, Program};
+use crate::config::config::{BType, ContextStack};
+use crate::error::span::{Span, Spanned};
+use crate::ast::exp::{Expr, IRObj};
+use crate::ast::node_id::NodeId;
+use crate::koopa_ir::config::KoopaOpCode;
+use crate::koopa_ir::koopa_ir::{
+    insert_elem_ptr, insert_instruction, runtime_funcs, Func, IRBlock, InstData, Operand, Param,
+    Program,
+};
+use crate::error::error::CompileError;
+use crate::semantic::semantic::{check_resolved, SemanticInfo};
 
 use std::rc::Rc;
 
 /// define the AST structure
 #[derive(Debug)]
 pub struct CompUnit {
-    pub global_decls: Vec<Decl>,
-    pub func_defs: Vec<FuncDef>,
+    // in source order, for a name is only visible after its declaration
+    pub items: Vec<GlobalItem>,
+    pub span: Span,
 }
 
 impl CompUnit {
-    pub fn parse(&self) -> Result<Program, Box<dyn std::error::Error>> {
+    pub fn global_decls(&self) -> impl Iterator<Item = &Decl> {
+        self.items.iter().filter_map(|item| match item {
+            GlobalItem::Decl(decl) => Some(decl),
+            GlobalItem::FuncDef(_) => None,
+        })
+    }
+
+    pub fn func_defs(&self) -> impl Iterator<Item = &FuncDef> {
+        self.items.iter().filter_map(|item| match item {
+            GlobalItem::Decl(_) => None,
+            GlobalItem::FuncDef(func_def) => Some(func_def),
+        })
+    }
+
+    /// lower the program checked by semantic analysis. the lowering state lives only
+    /// as long as this call, so a program could be lowered any number of times.
+    pub fn parse(&self, info: SemanticInfo) -> Result<Program, Vec<CompileError>> {
+        check_resolved(self, &info)?;
+
         // construct Program and return
         let mut program = Program::new();
-        for func in &self.func_defs {
-            program.push_func(Rc::clone(&func.parse()));
+        let ctx = &mut ContextStack::new(info);
+
+        // the runtime library is visible to the whole program
+        let runtime_funcs = runtime_funcs().into_iter().map(Rc::new).collect::<Vec<_>>();
+        for func in &runtime_funcs {
+            ctx.insert_func(Rc::clone(func));
+        }
+        // names of locals mustn't clash with the functions defined later
+        for func_def in self.func_defs() {
+            ctx.reserve_global_name(func_def.ident.clone());
+        }
+
+        // names are resolved already, so the globals still come first like in koopa ir
+        for decl in self.global_decls() {
+            for global_val in decl.parse_global(ctx) {
+                ctx.reserve_global_name(global_val.name.clone());
+                program.push_global_val(global_val);
+            }
+        }
+        for func in self.func_defs() {
+            program.push_func(func.parse(ctx));
+        }
+
+        // only the referenced library functions need to be declared
+        for func in runtime_funcs {
+            if program.is_called(&func.name) {
+                program.push_func_decl(func);
+            }
         }
         Ok(program)
     }
 }
 
+/// top-level item of a compile unit
+#[derive(Debug)]
+pub enum GlobalItem {
+    Decl(Decl),
+    FuncDef(FuncDef),
+}
+
 #[derive(Debug)]
 pub struct FuncDef {
     pub func_type: BType,
     pub ident: String,
+    pub params: Vec<FuncFParam>,
     pub block: Block,
+    pub span: Span,
 }
 
-impl FuncDef {
-    fn parse(&self) -> Rc<Func> {
-        // TODO: processing global value
+#[derive(Debug, Clone)]
+pub struct FuncFParam {
+    pub b_type: BType,
+    pub ident: String,
+    // Some for array parameters, holding dimensions after the first one
+    pub dims: Option<Vec<ConstExp>>,
+    pub span: Span,
+    pub id: NodeId,
+}
 
+impl FuncDef {
+    fn parse(&self, ctx: &mut ContextStack) -> Rc<Func> {
         // get func type and ident
         let func_type = &self.func_type;
         let func_name = self.ident.clone();
 
-        let mut func = Rc::new(Func::new(func_name, func_type.clone(), vec![]));
+        let params = self
+            .params
+            .iter()
+            .map(|param| Param {
+                name: param.ident.clone(),
+                // array parameters are passed as pointers to their first element
+                param_type: ctx.get_symbol(param.id).typ,
+            })
+            .collect();
 
-        CONTEXT_STACK.with(|stack| stack.borrow_mut().enter_func_scope(Rc::clone(&func)));
+        let func = Rc::new(Func::new(func_name, func_type.clone(), params));
+
+        // register the function before parsing its body so that it can call itself
+        ctx.insert_func(Rc::clone(&func));
+        ctx.enter_func_scope(Rc::clone(&func));
 
         {
-            let ir_block = Rc::new(IRBlock::new());
-            self.block.parse(Rc::clone(&ir_block));
-            let func_mut = Rc::get_mut(&mut func).unwrap();
-            func_mut.push_ir_block(ir_block);
+            let ir_block = Rc::new(IRBlock::new("entry".to_string()));
+            ctx.switch_ir_block(ir_block);
+
+            // copy params to allocated space, so that they could be assigned like local variables
+            for (param, param_type) in self.params.iter().zip(func.params.iter().map(|p| &p.param_type)) {
+                let name = ctx.alloc_pointer_name(&param.ident);
+                insert_instruction(ctx, InstData::new(
+                    param_type.clone(),
+                    IRObj::Pointer { name: name.clone() },
+                    KoopaOpCode::ALLOC,
+                    vec![Operand::BType(param_type.clone())],
+                ));
+                insert_instruction(ctx, InstData::new(
+                    BType::Void,
+                    IRObj::None,
+                    KoopaOpCode::STORE,
+                    vec![
+                        Operand::Param(param.ident.clone()),
+                        Operand::Pointer(name.clone()),
+                    ],
+                ));
+
+                let pointer = IRObj::Pointer { name };
+                ctx.bind(
+                    param.id,
+                    match param.dims {
+                        None => pointer,
+                        Some(_) => IRObj::Array {
+                            pointer: Box::new(pointer),
+                            typ: param_type.clone(),
+                            values: None,
+                        },
+                    },
+                );
+            }
+
+            self.block.parse(ctx);
+
+            // falling off the end returns nothing, or 0 like main in C
+            if !ctx.is_current_ir_block_terminated() {
+                insert_instruction(ctx, InstData::new(
+                    BType::Void,
+                    IRObj::None,
+                    KoopaOpCode::RET,
+                    vec![match func_type {
+                        BType::Void => Operand::None,
+                        typ => Operand::from_parse_result(IRObj::from(ConstValue::zero(typ))),
+                    }],
+                ));
+            }
         }
 
-        CONTEXT_STACK.with(|stack| stack.borrow_mut().exit_scope());
+        ctx.exit_scope();
 
         func
     }
@@ -58,17 +188,19 @@ impl FuncDef {
 #[derive(Debug, Clone)]
 pub struct Block {
     pub block_items: Vec<BlockItem>,
+    pub span: Span,
 }
 
 impl Block {
-    pub fn parse(&self, ir_block: Rc<IRBlock>) {
-        CONTEXT_STACK.with(|stack| stack.borrow_mut().enter_block_scope(ir_block));
-
+    /// names are resolved by semantic analysis, so a block needs no scope of its own.
+    pub fn parse(&self, ctx: &mut ContextStack) {
         for item in &self.block_items {
-            item.parse();
+            // items following br, jump or ret in the same block are unreachable
+            if ctx.is_current_ir_block_terminated() {
+                break;
+            }
+            item.parse(ctx);
         }
-
-        CONTEXT_STACK.with(|stack| stack.borrow_mut().exit_scope());
     }
 }
 
@@ -78,15 +210,20 @@ pub enum BlockItem {
     Stmt { stmt: Stmt },
 }
 
+impl Spanned for BlockItem {
+    fn span(&self) -> Span {
+        match self {
+            BlockItem::Decl { decl } => decl.span(),
+            BlockItem::Stmt { stmt } => stmt.span(),
+        }
+    }
+}
+
 impl BlockItem {
-    pub fn parse(&self) {
+    pub fn parse(&self, ctx: &mut ContextStack) {
         match self {
-            BlockItem::Decl { decl } => {
-                decl.parse();
-            }
-            BlockItem::Stmt { stmt } => {
-                stmt.parse();
-            }
+            BlockItem::Decl { decl } => decl.parse(ctx),
+            BlockItem::Stmt { stmt } => stmt.parse(ctx),
         }
     }
 }
@@ -94,4 +231,57 @@ impl BlockItem {
 #[derive(Debug, Clone)]
 pub struct LVal {
     pub ident: String,
+    pub indices: Vec<Expr>,
+    pub span: Span,
+    pub id: NodeId,
+}
+
+impl LVal {
+    /// compute the address of the element (or sub-array) referred by the indices,
+    /// together with the type it points to.
+    pub fn parse_elem_ptr(&self, ctx: &mut ContextStack, array: &IRObj) -> (Operand, BType) {
+        let (pointer, typ) = match array {
+            IRObj::Array { pointer, typ, .. } => (Operand::from_parse_result(*pointer.clone()), typ.clone()),
+            _ => unreachable!("only arrays are indexed"),
+        };
+
+        let mut indices = self.indices.iter();
+        let (mut elem_ptr, mut elem_typ) = (pointer, typ);
+
+        // array parameter is a pointer to its first element, so it's indexed with getptr
+        if let BType::Pointer(elem) = elem_typ.clone() {
+            if let Some(index) = indices.next() {
+                let base = insert_instruction(ctx, InstData::new(
+                    elem_typ.clone(),
+                    IRObj::InstId(ctx.get_next_inst_id()),
+                    KoopaOpCode::LOAD,
+                    vec![elem_ptr],
+                ));
+                let index = Operand::from_parse_result(index.parse_var_exp(ctx));
+                elem_ptr = Operand::from_parse_result(insert_elem_ptr(
+                    ctx,
+                    KoopaOpCode::GETPTR,
+                    Operand::from_parse_result(base),
+                    index,
+                    (*elem).clone(),
+                ));
+                elem_typ = *elem;
+            }
+        }
+
+        for index in indices {
+            let elem = elem_typ.get_elem();
+            let index = Operand::from_parse_result(index.parse_var_exp(ctx));
+            elem_ptr = Operand::from_parse_result(insert_elem_ptr(
+                ctx,
+                KoopaOpCode::GETELEMPTR,
+                elem_ptr,
+                index,
+                elem.clone(),
+            ));
+            elem_typ = elem;
+        }
+
+        (elem_ptr, elem_typ)
+    }
 }
@@ -0,0 +1,274 @@
+use crate::ast::op::*;
+use crate::config::config::BType;
+use crate::error::error::ErrorKind;
+
+/// value of an integer literal in radix 8, 10 or 16.
+/// like C, literals are unsigned 32-bit and reinterpreted as i32,
+/// so that 2147483648 could be negated to INT_MIN and 0xFFFFFFFF is -1.
+pub fn parse_int_literal(literal: &str, radix: u32) -> Result<i32, ErrorKind> {
+    let digits = match radix {
+        16 => &literal[2..],
+        _ => literal,
+    };
+    u32::from_str_radix(digits, radix)
+        .map(|value| value as i32)
+        .map_err(|_| ErrorKind::IntegerOutOfRange(literal.to_string()))
+}
+
+/// value of a decimal or hexadecimal float literal, rounded to the nearest f32.
+/// like C, literals beyond the range of f32 become infinity.
+pub fn parse_float_literal(literal: &str) -> f32 {
+    let hex = match literal.strip_prefix("0x").or_else(|| literal.strip_prefix("0X")) {
+        Some(hex) => hex,
+        None => return literal.parse().expect("decimal literal is matched by the lexer"),
+    };
+
+    // 0x<mantissa>p<exp> stands for mantissa * 2^exp, where the mantissa is in hex
+    let (mantissa, exp) = hex
+        .split_once(['p', 'P'])
+        .expect("hex float literal always has an exponent");
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let mantissa = int_part
+        .chars()
+        .chain(frac_part.chars())
+        .fold(0.0, |acc, c| acc * 16.0 + c.to_digit(16).unwrap() as f64);
+    if mantissa == 0.0 {
+        return 0.0;
+    }
+    let exp = exp.parse::<f64>().unwrap() - 4.0 * frac_part.len() as f64;
+    (mantissa * 2f64.powf(exp)) as f32
+}
+
+/// value of a constant expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstValue {
+    Int(i32),
+    Float(f32),
+}
+
+impl ConstValue {
+    /// value of elements left out of an initializer list.
+    pub fn zero(typ: &BType) -> Self {
+        ConstValue::Int(0).convert(typ)
+    }
+
+    pub fn get_type(&self) -> BType {
+        match self {
+            ConstValue::Int(_) => BType::Int,
+            ConstValue::Float(_) => BType::Float,
+        }
+    }
+
+    /// implicit conversion between int and float, floats are truncated toward zero like C.
+    pub fn convert(self, typ: &BType) -> Self {
+        match (self, typ) {
+            (ConstValue::Int(v), BType::Float) => ConstValue::Float(v as f32),
+            (ConstValue::Float(v), BType::Int) => ConstValue::Int(v as i32),
+            (value, _) => value,
+        }
+    }
+
+    pub fn as_int(self) -> i32 {
+        match self.convert(&BType::Int) {
+            ConstValue::Int(v) => v,
+            ConstValue::Float(_) => unreachable!(),
+        }
+    }
+
+    pub fn is_true(self) -> bool {
+        match self {
+            ConstValue::Int(v) => v != 0,
+            ConstValue::Float(v) => v != 0.0,
+        }
+    }
+
+    /// how the value is laid out in memory.
+    pub fn to_bits(self) -> i32 {
+        match self {
+            ConstValue::Int(v) => v,
+            ConstValue::Float(v) => v.to_bits() as i32,
+        }
+    }
+}
+
+/// values in koopa ir, where floats are written as their bits in hex, e.g. 0x3fc00000 for 1.5
+impl std::fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstValue::Int(v) => write!(f, "{}", v),
+            ConstValue::Float(v) => write!(f, "{:#010x}", v.to_bits()),
+        }
+    }
+}
+
+/// operands of a binary operator converted to their common type,
+/// which is float as long as either of them is float.
+enum Operands {
+    Int(i32, i32),
+    Float(f32, f32),
+}
+
+fn promote(l: ConstValue, r: ConstValue) -> Operands {
+    match (l, r) {
+        (ConstValue::Int(l), ConstValue::Int(r)) => Operands::Int(l, r),
+        (l, r) => match (l.convert(&BType::Float), r.convert(&BType::Float)) {
+            (ConstValue::Float(l), ConstValue::Float(r)) => Operands::Float(l, r),
+            _ => unreachable!(),
+        },
+    }
+}
+
+impl UnaryOp {
+    pub fn eval(&self, v: ConstValue) -> ConstValue {
+        match (self, v) {
+            (UnaryOp::Plus, v) => v,
+            (UnaryOp::Minus, ConstValue::Int(v)) => ConstValue::Int(v.wrapping_neg()),
+            (UnaryOp::Minus, ConstValue::Float(v)) => ConstValue::Float(-v),
+            (UnaryOp::Not, v) => ConstValue::Int(!v.is_true() as i32),
+        }
+    }
+}
+
+/// compile-time evaluation of operators, following the arithmetic of RISC-V:
+/// int results wrap around on overflow, and only integer division by zero is rejected.
+impl BinaryOp {
+    /// type of the result on operands of the common type typ,
+    /// None if the operator isn't defined on typ.
+    pub fn result_type(&self, typ: &BType) -> Option<BType> {
+        match (self, typ) {
+            (BinaryOp::Mod, BType::Float) => None,
+            (BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod | BinaryOp::Add | BinaryOp::Sub, _) => {
+                Some(typ.clone())
+            }
+            // comparisons and logical operators result in int
+            _ => Some(BType::Int),
+        }
+    }
+
+    /// result of && and || decided by the left operand alone, in which case the right
+    /// one isn't evaluated like C.
+    pub fn short_circuit(&self, l: ConstValue) -> Option<ConstValue> {
+        match self {
+            BinaryOp::And if !l.is_true() => Some(ConstValue::Int(0)),
+            BinaryOp::Or if l.is_true() => Some(ConstValue::Int(1)),
+            _ => None,
+        }
+    }
+
+    pub fn eval(&self, l: ConstValue, r: ConstValue) -> Result<ConstValue, ErrorKind> {
+        match self {
+            BinaryOp::And => return Ok(ConstValue::Int((l.is_true() && r.is_true()) as i32)),
+            BinaryOp::Or => return Ok(ConstValue::Int((l.is_true() || r.is_true()) as i32)),
+            _ => {}
+        }
+        match promote(l, r) {
+            Operands::Int(_, 0) if matches!(self, BinaryOp::Div | BinaryOp::Mod) => {
+                Err(ErrorKind::DivisionByZero)
+            }
+            // i32::MIN / -1 wraps to i32::MIN and i32::MIN % -1 is 0, as div and rem do
+            Operands::Int(l, r) => Ok(ConstValue::Int(match self {
+                BinaryOp::Mul => l.wrapping_mul(r),
+                BinaryOp::Div => l.wrapping_div(r),
+                BinaryOp::Mod => l.wrapping_rem(r),
+                BinaryOp::Add => l.wrapping_add(r),
+                BinaryOp::Sub => l.wrapping_sub(r),
+                op => op.compare(l, r) as i32,
+            })),
+            Operands::Float(l, r) => Ok(match self {
+                BinaryOp::Mul => ConstValue::Float(l * r),
+                BinaryOp::Div => ConstValue::Float(l / r),
+                BinaryOp::Mod => unreachable!("% on floats is rejected by semantic analysis"),
+                BinaryOp::Add => ConstValue::Float(l + r),
+                BinaryOp::Sub => ConstValue::Float(l - r),
+                op => ConstValue::Int(op.compare(l, r) as i32),
+            }),
+        }
+    }
+
+    fn compare<T: PartialOrd>(&self, l: T, r: T) -> bool {
+        match self {
+            BinaryOp::Lt => l < r,
+            BinaryOp::Gt => l > r,
+            BinaryOp::Le => l <= r,
+            BinaryOp::Ge => l >= r,
+            BinaryOp::Eq => l == r,
+            BinaryOp::Ne => l != r,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::driver::{lower, parse};
+
+    fn int(literal: &str, radix: u32) -> i32 {
+        parse_int_literal(literal, radix).unwrap()
+    }
+
+    fn eval(op: BinaryOp, l: i32, r: i32) -> Result<ConstValue, ErrorKind> {
+        op.eval(ConstValue::Int(l), ConstValue::Int(r))
+    }
+
+    #[test]
+    fn int_min() {
+        assert_eq!(int("2147483648", 10), i32::MIN);
+        // -2147483648 stays INT_MIN after negation
+        let negated = UnaryOp::Minus.eval(ConstValue::Int(int("2147483648", 10)));
+        assert_eq!(negated, ConstValue::Int(i32::MIN));
+        assert_eq!(int("2147483647", 10), i32::MAX);
+    }
+
+    #[test]
+    fn radixes() {
+        assert_eq!(int("0x1F", 16), 31);
+        assert_eq!(int("0Xff", 16), 255);
+        assert_eq!(int("0xFFFFFFFF", 16), -1);
+        assert_eq!(int("017", 8), 15);
+        assert_eq!(int("0", 8), 0);
+        assert_eq!(int("037777777777", 8), -1);
+    }
+
+    #[test]
+    fn out_of_range() {
+        for (literal, radix) in [("4294967296", 10), ("0x100000000", 16), ("040000000000", 8)] {
+            let result = parse_int_literal(literal, radix);
+            assert!(matches!(result, Err(ErrorKind::IntegerOutOfRange(l)) if l == literal));
+        }
+    }
+
+    #[test]
+    fn wrapping_arithmetic() {
+        assert_eq!(eval(BinaryOp::Add, i32::MAX, 1).unwrap(), ConstValue::Int(i32::MIN));
+        assert_eq!(eval(BinaryOp::Sub, i32::MIN, 1).unwrap(), ConstValue::Int(i32::MAX));
+        assert_eq!(eval(BinaryOp::Mul, 65536, 65536).unwrap(), ConstValue::Int(0));
+        assert_eq!(eval(BinaryOp::Div, i32::MIN, -1).unwrap(), ConstValue::Int(i32::MIN));
+        assert_eq!(eval(BinaryOp::Mod, i32::MIN, -1).unwrap(), ConstValue::Int(0));
+        assert_eq!(eval(BinaryOp::Mod, -7, 2).unwrap(), ConstValue::Int(-1));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert!(matches!(eval(BinaryOp::Div, 1, 0), Err(ErrorKind::DivisionByZero)));
+        assert!(matches!(eval(BinaryOp::Mod, 1, 0), Err(ErrorKind::DivisionByZero)));
+        // only integers are rejected
+        let quotient = BinaryOp::Div.eval(ConstValue::Float(1.0), ConstValue::Int(0));
+        assert_eq!(quotient.unwrap(), ConstValue::Float(f32::INFINITY));
+    }
+
+    #[test]
+    fn short_circuit_skips_division_by_zero() {
+        assert_eq!(BinaryOp::And.short_circuit(ConstValue::Int(0)), Some(ConstValue::Int(0)));
+        assert_eq!(BinaryOp::Or.short_circuit(ConstValue::Float(0.5)), Some(ConstValue::Int(1)));
+        assert_eq!(BinaryOp::And.short_circuit(ConstValue::Int(1)), None);
+        assert_eq!(BinaryOp::Or.short_circuit(ConstValue::Int(0)), None);
+
+        // the right operand of a constant is never evaluated
+        let lower = |source: &str| lower(&parse(source).unwrap());
+        assert!(lower("const int c = 0 && 1 / 0; int main() { return c; }").is_ok());
+        assert!(lower("const int c = 1 || 1 % 0; int main() { return c; }").is_ok());
+        let diagnostics = lower("const int c = 1 && 1 / 0; int main() { return c; }").err().unwrap();
+        assert!(matches!(diagnostics.errors[0].kind, ErrorKind::DivisionByZero));
+    }
+}
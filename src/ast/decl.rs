@@ -1,30 +1,56 @@
-use crate::ast::exp::{Exp, Expression, IRObj};
+use crate::ast::const_eval::ConstValue;
+use crate::ast::exp::{convert, Expr, IRObj};
+use crate::ast::node_id::NodeId;
 use crate::config::config::BType;
-use crate::config::config::CONTEXT_STACK;
-use crate::koopa_ir::config::{KoopaOpCode, PTR_ID_ALLOCATOR};
-use crate::koopa_ir::koopa_ir::{insert_instruction, InstData, Operand};
+use crate::config::config::ContextStack;
+use crate::error::error::{CompileError, ErrorKind};
+use crate::error::span::{Span, Spanned};
+use crate::koopa_ir::config::KoopaOpCode;
+use crate::koopa_ir::koopa_ir::{
+    insert_elem_ptr, insert_instruction, InstData, KoopaGlobalVal, Operand,
+};
+use crate::semantic::symbol::SymbolKind;
 
 use std::vec::Vec;
 
 pub trait Declaration {
-    fn parse(&self) -> IRObj;
+    fn parse(&self, ctx: &mut ContextStack) -> IRObj;
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Decl {
     ConstDecl { const_decl: ConstDecl },
     VarDecl { var_decl: VarDecl },
+    // declaration skipped by the parser for a syntax error
+    Error { span: Span },
+}
+
+impl Spanned for Decl {
+    fn span(&self) -> Span {
+        match self {
+            Decl::ConstDecl { const_decl } => const_decl.span,
+            Decl::VarDecl { var_decl } => var_decl.span,
+            Decl::Error { span } => *span,
+        }
+    }
 }
 
 impl Decl {
-    pub fn parse(&self) {
+    pub fn parse(&self, ctx: &mut ContextStack) {
         match self {
-            Decl::ConstDecl { const_decl } => {
-                const_decl.parse();
-            }
-            Decl::VarDecl { var_decl } => {
-                var_decl.parse();
-            }
+            Decl::ConstDecl { const_decl } => const_decl.parse(ctx),
+            Decl::VarDecl { var_decl } => var_decl.parse(ctx),
+            Decl::Error { .. } => unreachable!("program with syntax errors is never lowered"),
+        }
+    }
+
+    /// top-level declarations become global values instead of stack allocations.
+    pub fn parse_global(&self, ctx: &mut ContextStack) -> Vec<KoopaGlobalVal> {
+        match self {
+            Decl::ConstDecl { const_decl } => const_decl.parse_global(ctx),
+            Decl::VarDecl { var_decl } => var_decl.parse_global(ctx),
+            Decl::Error { .. } => unreachable!("program with syntax errors is never lowered"),
         }
     }
 }
@@ -33,67 +59,197 @@ impl Decl {
 pub struct ConstDecl {
     pub b_type: BType,
     pub const_defs: Vec<ConstDef>,
+    pub span: Span,
 }
 
 impl ConstDecl {
-    fn parse(&self) {
+    fn parse(&self, ctx: &mut ContextStack) {
+        for const_def in &self.const_defs {
+            let result = const_def.parse(ctx);
+            ctx.bind(const_def.id, result);
+        }
+    }
+
+    fn parse_global(&self, ctx: &mut ContextStack) -> Vec<KoopaGlobalVal> {
+        let mut global_vals = vec![];
         for const_def in &self.const_defs {
-            let result = const_def.parse();
-            CONTEXT_STACK.with(|stack| {
-                stack
-                    .borrow_mut()
-                    .insert_const(const_def.ident.clone(), result)
-            });
+            // scalar constants are folded, so they don't occupy any memory
+            let result = if const_def.dims.is_empty() {
+                const_def.parse(ctx)
+            } else {
+                let (typ, values) = const_def.get_values(ctx);
+                global_vals.push(KoopaGlobalVal::new(
+                    const_def.ident.clone(),
+                    typ.clone(),
+                    values.clone(),
+                ));
+                IRObj::Array {
+                    pointer: Box::new(IRObj::GlobalPointer {
+                        name: const_def.ident.clone(),
+                    }),
+                    typ,
+                    values: Some(values),
+                }
+            };
+
+            ctx.bind(const_def.id, result);
         }
+        global_vals
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct ConstDef {
     pub ident: String,
+    pub dims: Vec<ConstExp>,
     pub const_init_val: ConstInitVal,
+    pub span: Span,
+    pub id: NodeId,
+}
+
+impl ConstDef {
+    /// type and flattened values evaluated by semantic analysis
+    fn get_values(&self, ctx: &ContextStack) -> (BType, Vec<ConstValue>) {
+        let symbol = ctx.get_symbol(self.id);
+        match symbol.kind {
+            SymbolKind::Const(values) => (symbol.typ, values),
+            _ => unreachable!("constant definition resolves to a constant"),
+        }
+    }
 }
 
 impl Declaration for ConstDef {
-    fn parse(&self) -> IRObj {
-        if CONTEXT_STACK
-            .with(|stack| stack.borrow().get_current_pointer(self.ident.as_str()))
-            .is_some()
-        {
-            panic!(
-                "Cannot declare constant {} with the same name as a variable",
-                self.ident
+    fn parse(&self, ctx: &mut ContextStack) -> IRObj {
+        let (typ, values) = self.get_values(ctx);
+        if self.dims.is_empty() {
+            return IRObj::from(values[0]);
+        }
+
+        // constant arrays still live in memory, for they could be indexed by variables
+        let pointer = alloc_local(ctx, &self.ident, &typ);
+        init_local_array(
+            ctx,
+            &pointer,
+            &typ,
+            values
+                .iter()
+                .map(|value| Operand::from_parse_result(IRObj::from(*value)))
+                .collect(),
+        );
+
+        IRObj::Array {
+            pointer: Box::new(pointer),
+            typ,
+            values: Some(values),
+        }
+    }
+}
+
+/// initializer lists are flattened following the SysY rules
+pub trait InitList: Sized + Spanned {
+    fn get_exp(&self) -> Option<&Expr>;
+
+    fn get_list(&self) -> Option<&[Self]>;
+
+    /// flatten the initializer of array typ, None stands for an element filled with zero.
+    fn flatten(&self, typ: &BType) -> Result<Vec<Option<&Expr>>, CompileError> {
+        let mut flat = vec![];
+        match self.get_list() {
+            Some(list) => flatten_list(list, &typ.get_dims(), &mut flat)?,
+            None => {
+                return Err(ErrorKind::InvalidInitializer(
+                    "array must be initialized with an initializer list".to_string(),
+                )
+                .at(self.span()))
+            }
+        }
+        Ok(flat)
+    }
+}
+
+fn flatten_list<'a, T: InitList>(
+    list: &'a [T],
+    dims: &[u32],
+    flat: &mut Vec<Option<&'a Expr>>,
+) -> Result<(), CompileError> {
+    let total = dims.iter().product::<u32>() as usize;
+    let start = flat.len();
+
+    for init in list {
+        if flat.len() - start >= total {
+            return Err(
+                ErrorKind::InvalidInitializer("too many initializers for array".to_string())
+                    .at(init.span()),
             );
-        } else if CONTEXT_STACK
-            .with(|stack| stack.borrow().get_current_const(self.ident.as_str()))
-            .is_some()
-        {
-            panic!("Constant {} already declared", self.ident);
         }
 
-        self.const_init_val.parse()
+        match (init.get_exp(), init.get_list()) {
+            (Some(exp), _) => flat.push(Some(exp)),
+            (None, Some(sub_list)) => {
+                // a nested list initializes the largest sub-array aligned with current position
+                let offset = (flat.len() - start) as u32;
+                let sub_dims = (1..dims.len())
+                    .map(|i| &dims[i..])
+                    .find(|sub_dims| offset.is_multiple_of(sub_dims.iter().product::<u32>()))
+                    .ok_or_else(|| {
+                        ErrorKind::InvalidInitializer(
+                            "initializer list is not aligned with array dimensions".to_string(),
+                        )
+                        .at(init.span())
+                    })?;
+                flatten_list(sub_list, sub_dims, flat)?;
+            }
+            (None, None) => unreachable!(),
+        }
     }
+
+    // the rest elements are filled with zero
+    flat.resize(start + total, None);
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
-pub struct ConstInitVal {
-    pub const_exp: ConstExp,
+pub enum ConstInitVal {
+    ConstExp { const_exp: ConstExp },
+    List {
+        const_init_vals: Vec<ConstInitVal>,
+        span: Span,
+    },
 }
 
-impl Declaration for ConstInitVal {
-    fn parse(&self) -> IRObj {
-        self.const_exp.parse()
+impl Spanned for ConstInitVal {
+    fn span(&self) -> Span {
+        match self {
+            ConstInitVal::ConstExp { const_exp } => const_exp.span(),
+            ConstInitVal::List { span, .. } => *span,
+        }
+    }
+}
+
+impl InitList for ConstInitVal {
+    fn get_exp(&self) -> Option<&Expr> {
+        match self {
+            ConstInitVal::ConstExp { const_exp } => Some(&const_exp.exp),
+            ConstInitVal::List { .. } => None,
+        }
+    }
+
+    fn get_list(&self) -> Option<&[Self]> {
+        match self {
+            ConstInitVal::ConstExp { .. } => None,
+            ConstInitVal::List { const_init_vals, .. } => Some(const_init_vals),
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct ConstExp {
-    pub exp: Box<Exp>,
+    pub exp: Box<Expr>,
 }
 
-impl Declaration for ConstExp {
-    fn parse(&self) -> IRObj {
-        self.exp.parse_const_exp()
+impl Spanned for ConstExp {
+    fn span(&self) -> Span {
+        self.exp.span()
     }
 }
 
@@ -101,93 +257,299 @@ impl Declaration for ConstExp {
 pub struct VarDecl {
     pub b_type: BType,
     pub var_defs: Vec<VarDef>,
+    pub span: Span,
 }
 
 impl VarDecl {
-    fn parse(&self) {
+    fn parse(&self, ctx: &mut ContextStack) {
         for var_def in &self.var_defs {
-            let result = var_def.parse();
-            // insert pointer into pointer table for parsing first.
-            CONTEXT_STACK.with(|stack| {
-                stack
-                    .borrow_mut()
-                    .insert_pointer(var_def.ident.clone(), result)
-            });
+            let result = var_def.parse(ctx);
+            ctx.bind(var_def.id, result);
         }
     }
+
+    fn parse_global(&self, ctx: &mut ContextStack) -> Vec<KoopaGlobalVal> {
+        let mut global_vals = vec![];
+        for var_def in &self.var_defs {
+            let typ = var_def.get_type(ctx);
+            // initializer of a global variable is folded by semantic analysis
+            let fold = |exp: &Expr| {
+                ctx.get_const_value(exp.id())
+                    .expect("global initializer is folded")
+                    .convert(&self.b_type)
+            };
+            let init = match &var_def.init_val {
+                Some(InitVal::Exp { exp }) if var_def.dims.is_empty() => vec![fold(exp)],
+                Some(init_val) => init_val
+                    .flatten(&typ)
+                    .expect("initializer is checked by semantic analysis")
+                    .iter()
+                    .map(|exp| exp.map_or(ConstValue::zero(&self.b_type), fold))
+                    .collect(),
+                None => vec![ConstValue::zero(&self.b_type); (typ.size_in_bytes() / 4) as usize],
+            };
+
+            global_vals.push(KoopaGlobalVal::new(
+                var_def.ident.clone(),
+                typ.clone(),
+                init,
+            ));
+
+            let pointer = IRObj::GlobalPointer {
+                name: var_def.ident.clone(),
+            };
+            ctx.bind(
+                var_def.id,
+                if var_def.dims.is_empty() {
+                    pointer
+                } else {
+                    IRObj::Array {
+                        pointer: Box::new(pointer),
+                        typ,
+                        values: None,
+                    }
+                },
+            );
+        }
+        global_vals
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct VarDef {
     pub ident: String,
+    pub dims: Vec<ConstExp>,
     pub init_val: Option<InitVal>,
+    pub span: Span,
+    pub id: NodeId,
+}
+
+impl VarDef {
+    /// type resolved by semantic analysis
+    fn get_type(&self, ctx: &ContextStack) -> BType {
+        ctx.get_symbol(self.id).typ
+    }
+
+    fn parse_array(&self, ctx: &mut ContextStack) -> IRObj {
+        let typ = self.get_type(ctx);
+        let pointer = alloc_local(ctx, &self.ident, &typ);
+
+        // arrays without initializer are left uninitialized like C
+        if let Some(init_val) = &self.init_val {
+            let base = typ.get_base();
+            let values = init_val
+                .flatten(&typ)
+                .expect("initializer is checked by semantic analysis")
+                .iter()
+                .map(|exp| match exp {
+                    Some(exp) => {
+                        let value = exp.parse_var_exp(ctx);
+                        Operand::from_parse_result(convert(ctx, value, &base))
+                    }
+                    None => Operand::from_parse_result(IRObj::from(ConstValue::zero(&base))),
+                })
+                .collect();
+            init_local_array(ctx, &pointer, &typ, values);
+        }
+
+        IRObj::Array {
+            pointer: Box::new(pointer),
+            typ,
+            values: None,
+        }
+    }
 }
 
 impl Declaration for VarDef {
-    fn parse(&self) -> IRObj {
-        // semantic check
-        if CONTEXT_STACK
-            .with(|stack| stack.borrow().get_current_const(self.ident.as_str()))
-            .is_some()
-        {
-            panic!(
-                "Cannot declare variable {} with the same name as a constant",
-                self.ident
-            );
-        } else if CONTEXT_STACK
-            .with(|stack| stack.borrow().get_current_pointer(self.ident.as_str()))
-            .is_some()
-        {
-            panic!("Variable {} already declared", self.ident);
+    fn parse(&self, ctx: &mut ContextStack) -> IRObj {
+        if !self.dims.is_empty() {
+            return self.parse_array(ctx);
         }
 
-        let pointer_id = PTR_ID_ALLOCATOR.with(|allocator| allocator.borrow_mut().alloc());
         // whatever the init_val is, we need to allocate space for the variable
-        insert_instruction(InstData::new(
-            BType::Int,
-            IRObj::Pointer {
-                initialized: self.init_val.is_some(),
-                pointer_id, // placeholder, will be replaced
-            },
-            KoopaOpCode::ALLOC,
-            vec![Operand::BType(BType::Int)],
-        ));
+        let typ = self.get_type(ctx);
+        let pointer = alloc_local(ctx, &self.ident, &typ);
 
         if let Some(init_val) = &self.init_val {
-            let parse_result = init_val.parse();
             // we don't need to store temp var to var_table here for it'll be removed soon after STORE
-            if let IRObj::Const(_) | IRObj::InstId(_) = parse_result {
-                insert_instruction(InstData::new(
-                    BType::Void,
-                    IRObj::None,
-                    KoopaOpCode::STORE,
-                    vec![
-                        match parse_result {
-                            IRObj::InstId(id) => Operand::InstId(id),
-                            IRObj::Const(c) => Operand::Const(c),
-                            _ => unreachable!(),
-                        },
-                        // the allocated address
-                        Operand::Pointer(pointer_id),
-                    ],
-                ));
-            }
+            let value = init_val.parse(ctx);
+            let value = Operand::from_parse_result(convert(ctx, value, &typ));
+            insert_instruction(ctx, InstData::new(
+                BType::Void,
+                IRObj::None,
+                KoopaOpCode::STORE,
+                vec![
+                    value,
+                    // the allocated address
+                    Operand::from_parse_result(pointer.clone()),
+                ],
+            ));
         };
 
-        IRObj::Pointer {
-            initialized: self.init_val.is_some(),
-            pointer_id,
+        pointer
+    }
+}
+
+/// allocate space for a local variable of typ, named after its identifier.
+fn alloc_local(ctx: &ContextStack, ident: &str, typ: &BType) -> IRObj {
+    let name = ctx.alloc_pointer_name(ident);
+    insert_instruction(ctx, InstData::new(
+        typ.clone(),
+        IRObj::Pointer { name: name.clone() },
+        KoopaOpCode::ALLOC,
+        vec![Operand::BType(typ.clone())],
+    ));
+
+    IRObj::Pointer { name }
+}
+
+/// store the flattened values into every element of a local array.
+fn init_local_array(ctx: &ContextStack, pointer: &IRObj, typ: &BType, values: Vec<Operand>) {
+    let dims = typ.get_dims();
+    for (i, value) in values.into_iter().enumerate() {
+        // walk down the dimensions to the address of the i-th element
+        let mut elem_ptr = Operand::from_parse_result(pointer.clone());
+        let mut elem_typ = typ.clone();
+        let mut stride = dims.iter().product::<u32>() as usize;
+        for len in &dims {
+            stride /= *len as usize;
+            elem_typ = elem_typ.get_elem();
+            elem_ptr = Operand::from_parse_result(insert_elem_ptr(
+                ctx,
+                KoopaOpCode::GETELEMPTR,
+                elem_ptr,
+                Operand::Const(((i / stride) % *len as usize) as i32),
+                elem_typ.clone(),
+            ));
         }
+
+        insert_instruction(ctx, InstData::new(
+            BType::Void,
+            IRObj::None,
+            KoopaOpCode::STORE,
+            vec![value, elem_ptr],
+        ));
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct InitVal {
-    pub exp: Box<Exp>,
+pub enum InitVal {
+    Exp { exp: Box<Expr> },
+    List {
+        init_vals: Vec<InitVal>,
+        span: Span,
+    },
+}
+
+impl Spanned for InitVal {
+    fn span(&self) -> Span {
+        match self {
+            InitVal::Exp { exp } => exp.span(),
+            InitVal::List { span, .. } => *span,
+        }
+    }
+}
+
+impl InitList for InitVal {
+    fn get_exp(&self) -> Option<&Expr> {
+        match self {
+            InitVal::Exp { exp } => Some(exp),
+            InitVal::List { .. } => None,
+        }
+    }
+
+    fn get_list(&self) -> Option<&[Self]> {
+        match self {
+            InitVal::Exp { .. } => None,
+            InitVal::List { init_vals, .. } => Some(init_vals),
+        }
+    }
 }
 
 impl Declaration for InitVal {
-    fn parse(&self) -> IRObj {
-        self.exp.parse_var_exp()
+    fn parse(&self, ctx: &mut ContextStack) -> IRObj {
+        match self {
+            InitVal::Exp { exp } => exp.parse_var_exp(ctx),
+            InitVal::List { .. } => unreachable!("scalar initializer is checked by semantic analysis"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decl, InitList};
+    use crate::ast::ast::GlobalItem;
+    use crate::ast::exp::Expr;
+    use crate::config::config::BType;
+    use crate::driver::driver::parse;
+
+    /// flatten the initializer of `int a<dims> = <init>;`, giving the literals and
+    /// None for the elements filled with zero
+    fn flatten(dims: &[u32], init: &str) -> Result<Vec<Option<i32>>, String> {
+        let brackets = dims.iter().map(|len| format!("[{}]", len)).collect::<String>();
+        let ast = parse(&format!("int a{} = {};", brackets, init)).unwrap();
+        let var_def = match &ast.items[0] {
+            GlobalItem::Decl(Decl::VarDecl { var_decl }) => &var_decl.var_defs[0],
+            _ => unreachable!(),
+        };
+        let typ = BType::array_of(BType::Int, dims);
+        let flat = var_def.init_val.as_ref().unwrap().flatten(&typ).map_err(|e| e.to_string())?;
+        Ok(flat
+            .into_iter()
+            .map(|exp| exp.map(|exp| match exp {
+                Expr::Number { value, .. } => *value,
+                _ => unreachable!(),
+            }))
+            .collect())
+    }
+
+    #[test]
+    fn flat_list_fills_rows_in_order() {
+        assert_eq!(
+            flatten(&[2, 3], "{1, 2, 3, 4}").unwrap(),
+            [Some(1), Some(2), Some(3), Some(4), None, None]
+        );
+        assert_eq!(flatten(&[3], "{}").unwrap(), [None, None, None]);
+    }
+
+    #[test]
+    fn nested_lists_start_a_sub_array() {
+        assert_eq!(
+            flatten(&[2, 3], "{{1}, {2, 3}}").unwrap(),
+            [Some(1), None, None, Some(2), Some(3), None]
+        );
+        // a list in the middle of a row initializes the largest sub-array aligned there
+        assert_eq!(
+            flatten(&[2, 2, 2], "{1, 2, {3}, {4, 5}}").unwrap(),
+            [Some(1), Some(2), Some(3), None, Some(4), Some(5), None, None]
+        );
+        assert_eq!(
+            flatten(&[2, 2, 2], "{{1, 2, {3}}, 4}").unwrap(),
+            [Some(1), Some(2), Some(3), None, Some(4), None, None, None]
+        );
+    }
+
+    #[test]
+    fn empty_lists_are_zero_filled() {
+        assert_eq!(
+            flatten(&[2, 3], "{{}, {1}}").unwrap(),
+            [None, None, None, Some(1), None, None]
+        );
+        assert_eq!(
+            flatten(&[2, 2, 2], "{{{}, {1}}, {}}").unwrap(),
+            [None, None, Some(1), None, None, None, None, None]
+        );
+    }
+
+    #[test]
+    fn invalid_lists() {
+        let too_many = "invalid initializer: too many initializers for array";
+        assert_eq!(flatten(&[2], "{1, 2, 3}").unwrap_err(), too_many);
+        assert_eq!(flatten(&[2, 2], "{{1, 2, 3}}").unwrap_err(), too_many);
+        assert_eq!(flatten(&[2, 2], "{{1}, {2}, {3}}").unwrap_err(), too_many);
+
+        // no sub-array of a row starts in the middle of it
+        let misaligned = "invalid initializer: initializer list is not aligned with array dimensions";
+        assert_eq!(flatten(&[2, 3], "{1, {2, 3}}").unwrap_err(), misaligned);
     }
 }
@@ -0,0 +1,488 @@
+use crate::ast::ast::{Block, BlockItem, CompUnit, FuncDef, FuncFParam, GlobalItem, LVal};
+use crate::ast::decl::{ConstDecl, ConstDef, ConstExp, ConstInitVal, Decl, InitVal, VarDecl, VarDef};
+use crate::ast::exp::Expr;
+use crate::ast::stmt::Stmt;
+use crate::error::span::Span;
+
+use std::fmt::Write;
+
+/// how the AST is printed
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum DumpFormat {
+    // (Kind start..end attr=value child...)
+    Sexpr,
+    // one object per node, with the fields named like the AST
+    Json,
+    // one node per line, indented by depth
+    Tree,
+}
+
+/// scalar field of a node
+pub enum Attr {
+    Str(String),
+    Int(i64),
+    Float(f32),
+}
+
+impl std::fmt::Display for Attr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Attr::Str(s) => write!(f, "{}", s),
+            Attr::Int(v) => write!(f, "{}", v),
+            Attr::Float(v) => write!(f, "{:?}", v),
+        }
+    }
+}
+
+/// field holding other nodes
+pub enum Child {
+    One(Node),
+    Many(Vec<Node>),
+}
+
+/// shape of an AST node shared by all the dump formats
+pub struct Node {
+    pub kind: &'static str,
+    pub span: Span,
+    pub attrs: Vec<(&'static str, Attr)>,
+    pub children: Vec<(&'static str, Child)>,
+}
+
+impl Node {
+    fn new(kind: &'static str, span: Span) -> Self {
+        Node {
+            kind,
+            span,
+            attrs: vec![],
+            children: vec![],
+        }
+    }
+
+    fn attr(mut self, name: &'static str, value: Attr) -> Self {
+        self.attrs.push((name, value));
+        self
+    }
+
+    fn str(self, name: &'static str, value: &str) -> Self {
+        self.attr(name, Attr::Str(value.to_string()))
+    }
+
+    fn child(mut self, name: &'static str, node: &impl ToNode) -> Self {
+        self.children.push((name, Child::One(node.to_node())));
+        self
+    }
+
+    /// None is left out.
+    fn opt_child<T: ToNode>(self, name: &'static str, node: Option<&T>) -> Self {
+        match node {
+            Some(node) => self.child(name, node),
+            None => self,
+        }
+    }
+
+    fn children<'a, T: ToNode + 'a>(mut self, name: &'static str, nodes: impl IntoIterator<Item = &'a T>) -> Self {
+        let nodes = nodes.into_iter().map(ToNode::to_node).collect();
+        self.children.push((name, Child::Many(nodes)));
+        self
+    }
+
+    pub fn dump(&self, format: DumpFormat) -> String {
+        let mut out = String::new();
+        match format {
+            DumpFormat::Sexpr => self.write_sexpr(&mut out),
+            DumpFormat::Json => self.write_json(&mut out),
+            DumpFormat::Tree => self.write_tree(&mut out, 0),
+        }
+        if format != DumpFormat::Tree {
+            out.push('\n');
+        }
+        out
+    }
+
+    fn write_sexpr(&self, out: &mut String) {
+        write!(out, "({} {}..{}", self.kind, self.span.start, self.span.end).unwrap();
+        for (name, value) in &self.attrs {
+            match value {
+                Attr::Str(s) => write!(out, " {}={}", name, quote(s)).unwrap(),
+                value => write!(out, " {}={}", name, value).unwrap(),
+            }
+        }
+        // children are told apart by their kinds and order
+        for (_, child) in &self.children {
+            let nodes = match child {
+                Child::One(node) => std::slice::from_ref(node),
+                Child::Many(nodes) => nodes,
+            };
+            for node in nodes {
+                out.push(' ');
+                node.write_sexpr(out);
+            }
+        }
+        out.push(')');
+    }
+
+    fn write_json(&self, out: &mut String) {
+        write!(
+            out,
+            "{{\"kind\":{},\"span\":[{},{}]",
+            quote(self.kind),
+            self.span.start,
+            self.span.end
+        )
+        .unwrap();
+        for (name, value) in &self.attrs {
+            match value {
+                Attr::Str(s) => write!(out, ",\"{}\":{}", name, quote(s)).unwrap(),
+                Attr::Int(v) => write!(out, ",\"{}\":{}", name, v).unwrap(),
+                // json has no inf, which an overflowing literal becomes
+                Attr::Float(v) if !v.is_finite() => write!(out, ",\"{}\":null", name).unwrap(),
+                Attr::Float(v) => write!(out, ",\"{}\":{:?}", name, v).unwrap(),
+            }
+        }
+        for (name, child) in &self.children {
+            write!(out, ",\"{}\":", name).unwrap();
+            match child {
+                Child::One(node) => node.write_json(out),
+                Child::Many(nodes) => {
+                    out.push('[');
+                    for (i, node) in nodes.iter().enumerate() {
+                        if i > 0 {
+                            out.push(',');
+                        }
+                        node.write_json(out);
+                    }
+                    out.push(']');
+                }
+            }
+        }
+        out.push('}');
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        write!(out, "{}{} [{}..{}]", "  ".repeat(depth), self.kind, self.span.start, self.span.end).unwrap();
+        for (name, value) in &self.attrs {
+            write!(out, " {}={}", name, value).unwrap();
+        }
+        out.push('\n');
+        for (name, child) in &self.children {
+            match child {
+                Child::One(node) => {
+                    writeln!(out, "{}{}:", "  ".repeat(depth + 1), name).unwrap();
+                    node.write_tree(out, depth + 2);
+                }
+                // empty lists, e.g. params of `main()`, are left out
+                Child::Many(nodes) if nodes.is_empty() => {}
+                Child::Many(nodes) => {
+                    writeln!(out, "{}{}:", "  ".repeat(depth + 1), name).unwrap();
+                    for node in nodes {
+                        node.write_tree(out, depth + 2);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// quote s as a json string, which is also readable in s-expressions.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// convert an AST node to its dump shape
+pub trait ToNode {
+    fn to_node(&self) -> Node;
+}
+
+impl<T: ToNode> ToNode for Box<T> {
+    fn to_node(&self) -> Node {
+        self.as_ref().to_node()
+    }
+}
+
+impl ToNode for CompUnit {
+    fn to_node(&self) -> Node {
+        Node::new("CompUnit", self.span).children("items", &self.items)
+    }
+}
+
+impl ToNode for GlobalItem {
+    fn to_node(&self) -> Node {
+        match self {
+            GlobalItem::Decl(decl) => decl.to_node(),
+            GlobalItem::FuncDef(func_def) => func_def.to_node(),
+        }
+    }
+}
+
+impl ToNode for FuncDef {
+    fn to_node(&self) -> Node {
+        Node::new("FuncDef", self.span)
+            .str("func_type", self.func_type.as_str())
+            .str("ident", &self.ident)
+            .children("params", &self.params)
+            .child("block", &self.block)
+    }
+}
+
+impl ToNode for FuncFParam {
+    fn to_node(&self) -> Node {
+        let node = Node::new("FuncFParam", self.span)
+            .str("b_type", self.b_type.as_str())
+            .str("ident", &self.ident);
+        // the first dimension of an array parameter is omitted, i.e. `int a[][3]`
+        match &self.dims {
+            Some(dims) => node.children("dims", dims),
+            None => node,
+        }
+    }
+}
+
+impl ToNode for Block {
+    fn to_node(&self) -> Node {
+        Node::new("Block", self.span).children("block_items", &self.block_items)
+    }
+}
+
+impl ToNode for BlockItem {
+    fn to_node(&self) -> Node {
+        match self {
+            BlockItem::Decl { decl } => decl.to_node(),
+            BlockItem::Stmt { stmt } => stmt.to_node(),
+        }
+    }
+}
+
+impl ToNode for Decl {
+    fn to_node(&self) -> Node {
+        match self {
+            Decl::ConstDecl { const_decl } => const_decl.to_node(),
+            Decl::VarDecl { var_decl } => var_decl.to_node(),
+            Decl::Error { span } => Node::new("Error", *span),
+        }
+    }
+}
+
+impl ToNode for ConstDecl {
+    fn to_node(&self) -> Node {
+        Node::new("ConstDecl", self.span)
+            .str("b_type", self.b_type.as_str())
+            .children("const_defs", &self.const_defs)
+    }
+}
+
+impl ToNode for ConstDef {
+    fn to_node(&self) -> Node {
+        Node::new("ConstDef", self.span)
+            .str("ident", &self.ident)
+            .children("dims", &self.dims)
+            .child("const_init_val", &self.const_init_val)
+    }
+}
+
+impl ToNode for ConstInitVal {
+    fn to_node(&self) -> Node {
+        match self {
+            ConstInitVal::ConstExp { const_exp } => const_exp.to_node(),
+            ConstInitVal::List {
+                const_init_vals,
+                span,
+            } => Node::new("InitList", *span).children("init_vals", const_init_vals),
+        }
+    }
+}
+
+impl ToNode for ConstExp {
+    fn to_node(&self) -> Node {
+        self.exp.to_node()
+    }
+}
+
+impl ToNode for VarDecl {
+    fn to_node(&self) -> Node {
+        Node::new("VarDecl", self.span)
+            .str("b_type", self.b_type.as_str())
+            .children("var_defs", &self.var_defs)
+    }
+}
+
+impl ToNode for VarDef {
+    fn to_node(&self) -> Node {
+        Node::new("VarDef", self.span)
+            .str("ident", &self.ident)
+            .children("dims", &self.dims)
+            .opt_child("init_val", self.init_val.as_ref())
+    }
+}
+
+impl ToNode for InitVal {
+    fn to_node(&self) -> Node {
+        match self {
+            InitVal::Exp { exp } => exp.to_node(),
+            InitVal::List { init_vals, span } => {
+                Node::new("InitList", *span).children("init_vals", init_vals)
+            }
+        }
+    }
+}
+
+impl ToNode for Stmt {
+    fn to_node(&self) -> Node {
+        match self {
+            Stmt::RegularStmt { l_val, exp, span } => Node::new("Assign", *span)
+                .child("l_val", l_val)
+                .child("exp", exp),
+            Stmt::RawExp { exp, span } => Node::new("ExpStmt", *span).opt_child("exp", exp.as_ref()),
+            Stmt::Block { block } => block.to_node(),
+            Stmt::If {
+                cond,
+                then_stmt,
+                else_stmt,
+                span,
+            } => Node::new("If", *span)
+                .child("cond", cond)
+                .child("then_stmt", then_stmt)
+                .opt_child("else_stmt", else_stmt.as_ref()),
+            Stmt::While { cond, body, span } => Node::new("While", *span)
+                .child("cond", cond)
+                .child("body", body),
+            Stmt::Break { span } => Node::new("Break", *span),
+            Stmt::Continue { span } => Node::new("Continue", *span),
+            Stmt::ReturnStmt { exp, span } => Node::new("Return", *span).opt_child("exp", exp.as_ref()),
+            Stmt::Error { span } => Node::new("Error", *span),
+        }
+    }
+}
+
+impl ToNode for LVal {
+    fn to_node(&self) -> Node {
+        Node::new("LVal", self.span)
+            .str("ident", &self.ident)
+            .children("indices", &self.indices)
+    }
+}
+
+impl ToNode for Expr {
+    fn to_node(&self) -> Node {
+        match self {
+            Expr::Binary { op, lhs, rhs, span, .. } => Node::new("Binary", *span)
+                .str("op", op.as_str())
+                .child("lhs", lhs)
+                .child("rhs", rhs),
+            Expr::Unary { op, exp, span, .. } => Node::new("Unary", *span)
+                .str("op", op.as_str())
+                .child("exp", exp),
+            Expr::Call { ident, args, span, .. } => Node::new("Call", *span)
+                .str("ident", ident)
+                .children("args", args),
+            Expr::Number { value, span, .. } => {
+                Node::new("Number", *span).attr("value", Attr::Int(*value as i64))
+            }
+            Expr::FloatNumber { value, span, .. } => {
+                Node::new("FloatNumber", *span).attr("value", Attr::Float(*value))
+            }
+            Expr::LVal { l_val } => l_val.to_node(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DumpFormat, Node, ToNode};
+    use crate::driver::driver::parse;
+    use crate::error::span::Span;
+
+    const SOURCE: &str = "int g[2] = {1};\nint main() { return -g[0] + 2.5; }";
+
+    fn dump(source: &str, format: DumpFormat) -> String {
+        parse(source).unwrap().to_node().dump(format)
+    }
+
+    #[test]
+    fn sexpr() {
+        let expected = concat!(
+            "(CompUnit 0..50 (VarDecl 0..15 b_type=\"int\" (VarDef 4..14 ident=\"g\" (Number 6..7 value=2) ",
+            "(InitList 11..14 (Number 12..13 value=1)))) (FuncDef 16..50 func_type=\"int\" ident=\"main\" ",
+            "(Block 27..50 (Return 29..48 (Binary 36..47 op=\"+\" (Unary 36..41 op=\"-\" ",
+            "(LVal 37..41 ident=\"g\" (Number 39..40 value=0))) (FloatNumber 44..47 value=2.5))))))\n",
+        );
+        assert_eq!(dump(SOURCE, DumpFormat::Sexpr), expected);
+    }
+
+    #[test]
+    fn json() {
+        let out = dump(SOURCE, DumpFormat::Json);
+        let expected = concat!(
+            r#"{"kind":"CompUnit","span":[0,50],"items":[{"kind":"VarDecl","span":[0,15],"b_type":"int","#,
+            r#""var_defs":[{"kind":"VarDef","span":[4,14],"ident":"g","dims":[{"kind":"Number","span":[6,7],"#,
+            r#""value":2}],"init_val":{"kind":"InitList","span":[11,14],"init_vals":[{"kind":"Number","#,
+            r#""span":[12,13],"value":1}]}}]},{"kind":"FuncDef","span":[16,50],"func_type":"int","#,
+            r#""ident":"main","params":[],"block":{"kind":"Block","span":[27,50],"block_items":[{"#,
+            r#""kind":"Return","span":[29,48],"exp":{"kind":"Binary","span":[36,47],"op":"+","lhs":{"#,
+            r#""kind":"Unary","span":[36,41],"op":"-","exp":{"kind":"LVal","span":[37,41],"ident":"g","#,
+            r#""indices":[{"kind":"Number","span":[39,40],"value":0}]}},"rhs":{"kind":"FloatNumber","#,
+            r#""span":[44,47],"value":2.5}}}]}}]}"#,
+            "\n",
+        );
+        assert_eq!(out, expected);
+
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["items"][1]["ident"], "main");
+        assert_eq!(value["items"][1]["block"]["block_items"][0]["exp"]["rhs"]["value"], 2.5);
+    }
+
+    #[test]
+    fn json_is_valid_for_any_string() {
+        // identifiers of the grammar never need escaping, so the node is built by hand
+        let ident = "a\"b\\c\nd\u{1}é";
+        let node = Node::new("LVal", Span::new(0, 1)).str("ident", ident);
+        let value: serde_json::Value = serde_json::from_str(&node.dump(DumpFormat::Json)).unwrap();
+        assert_eq!(value["ident"], ident);
+
+        // floats overflowing to inf become null
+        let out = dump("float f = 1e40;", DumpFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert!(value["items"][0]["var_defs"][0]["init_val"]["value"].is_null());
+    }
+
+    #[test]
+    fn tree() {
+        let expected = r#"CompUnit [0..50]
+  items:
+    VarDecl [0..15] b_type=int
+      var_defs:
+        VarDef [4..14] ident=g
+          dims:
+            Number [6..7] value=2
+          init_val:
+            InitList [11..14]
+              init_vals:
+                Number [12..13] value=1
+    FuncDef [16..50] func_type=int ident=main
+      block:
+        Block [27..50]
+          block_items:
+            Return [29..48]
+              exp:
+                Binary [36..47] op=+
+                  lhs:
+                    Unary [36..41] op=-
+                      exp:
+                        LVal [37..41] ident=g
+                          indices:
+                            Number [39..40] value=0
+                  rhs:
+                    FloatNumber [44..47] value=2.5
+"#;
+        assert_eq!(dump(SOURCE, DumpFormat::Tree), expected);
+    }
+}
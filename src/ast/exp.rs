@@ -1,665 +1,556 @@
 use crate::ast::ast::LVal;
-use crate::ast::op::*;
-use crate::config::config::CONTEXT_STACK;
+use crate::ast::const_eval::ConstValue;
+use crate::ast::node_id::{NodeId, UNNUMBERED};
+use crate::ast::op::{BinaryOp, UnaryOp};
+use crate::config::config::ContextStack;
 
 use crate::config::config::BType;
+use crate::error::span::{Span, Spanned};
 use crate::koopa_ir::config::KoopaOpCode;
-use crate::koopa_ir::koopa_ir::{insert_instruction, InstData, InstId};
+use crate::koopa_ir::koopa_ir::{
+    insert_elem_ptr, insert_instruction, IRBlock, InstData, InstId, Operand,
+};
+
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum IRObj {
     InstId(InstId), // temp variable, display in format "%id"
     Const(i32),     // constant value, display in literal
-    Pointer { initialized: bool, pointer_id: u32 }, // pointer to a variable in memory, display in format "@pointer_id"
+    FloatConst(f32), // float constant, display in hex bits
+    Pointer { name: String }, // pointer to a variable in memory, display in format "@name"
+    GlobalPointer { name: String }, // pointer to a global variable, display in format "@name"
+    // array or array parameter stored at pointer, values are kept for constant arrays
+    Array {
+        pointer: Box<IRObj>,
+        typ: BType,
+        values: Option<Vec<ConstValue>>,
+    },
     None,
 }
 
 impl IRObj {
-    pub fn get_value(&self) -> i32 {
+    pub fn get_const(&self) -> ConstValue {
         match self {
-            IRObj::Const(v) => *v,
+            IRObj::Const(v) => ConstValue::Int(*v),
+            IRObj::FloatConst(v) => ConstValue::Float(*v),
             _ => panic!("Not a constant value: {:?}", self),
         }
     }
 
-    pub fn get_id(&self) -> InstId {
+    /// type of a value, which decides between int and float operations.
+    pub fn get_type(&self, ctx: &ContextStack) -> BType {
         match self {
-            IRObj::InstId(id) => *id,
-            _ => panic!("Not an instruction ID: {:?}", self),
+            IRObj::Const(_) => BType::Int,
+            IRObj::FloatConst(_) => BType::Float,
+            IRObj::InstId(id) => ctx.get_current_dfg().borrow().get_inst(id).unwrap().typ.clone(),
+            _ => unreachable!("only values are typed: {:?}", self),
         }
     }
 
-    pub fn to_string(&self) -> String {
+    pub fn get_id(&self) -> InstId {
         match self {
-            IRObj::InstId(id) => format!("%{}", id),
-            IRObj::Const(c) => format!("{}", c),
-            IRObj::Pointer {
-                initialized: _,
-                pointer_id,
-            } => format!("@{}", pointer_id),
-            IRObj::None => "".to_string(),
+            IRObj::InstId(id) => *id,
+            _ => panic!("Not an instruction ID: {:?}", self),
         }
     }
 }
 
-pub trait Expression {
-    fn parse_var_exp(&self) -> IRObj;
-
-    fn parse_const_exp(&self) -> IRObj;
-}
-
-#[derive(Debug, Clone)]
-pub enum Exp {
-    LOrExp { lor_exp: Box<LOrExp> },
-}
-
-impl Expression for Exp {
-    /// parse_unary_exp
-    fn parse_var_exp(&self) -> IRObj {
+impl std::fmt::Display for IRObj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Exp::LOrExp { lor_exp } => return lor_exp.parse_var_exp(),
+            IRObj::InstId(id) => write!(f, "%{}", id),
+            IRObj::Const(c) => write!(f, "{}", c),
+            IRObj::FloatConst(c) => write!(f, "{}", ConstValue::Float(*c)),
+            IRObj::Pointer { name } => write!(f, "@{}", name),
+            IRObj::GlobalPointer { name } => write!(f, "@{}", name),
+            IRObj::Array { pointer, .. } => write!(f, "{}", pointer),
+            IRObj::None => Ok(()),
         }
     }
+}
 
-    fn parse_const_exp(&self) -> IRObj {
-        match self {
-            Exp::LOrExp { lor_exp } => return lor_exp.parse_const_exp(),
+impl From<ConstValue> for IRObj {
+    fn from(value: ConstValue) -> Self {
+        match value {
+            ConstValue::Int(v) => IRObj::Const(v),
+            ConstValue::Float(v) => IRObj::FloatConst(v),
         }
     }
 }
 
+/// expression of any precedence, whose grouping is decided by the parser
 #[derive(Debug, Clone)]
-pub enum LOrExp {
-    LAndExp {
-        land_exp: Box<LAndExp>,
+pub enum Expr {
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span,
+        id: NodeId,
     },
-    LOrExp {
-        lor_exp: Box<LOrExp>,
-        lor_op: LOrOp,
-        land_exp: Box<LAndExp>,
+    Unary {
+        op: UnaryOp,
+        exp: Box<Expr>,
+        span: Span,
+        id: NodeId,
     },
+    Call {
+        ident: String,
+        args: Vec<Expr>,
+        span: Span,
+        id: NodeId,
+    },
+    Number { value: i32, span: Span, id: NodeId },
+    FloatNumber { value: f32, span: Span, id: NodeId },
+    // shares the id of the lvalue
+    LVal { l_val: LVal },
 }
 
-impl Expression for LOrExp {
-    fn parse_var_exp(&self) -> IRObj {
-        match self {
-            LOrExp::LAndExp { land_exp } => {
-                return land_exp.parse_var_exp();
-            }
-
-            LOrExp::LOrExp {
-                lor_exp,
-                lor_op,
-                land_exp,
-            } => {
-                let left = lor_exp.parse_var_exp();
-                let right = land_exp.parse_var_exp();
-
-                let koopa_op = match lor_op {
-                    LOrOp::Or => KoopaOpCode::OR,
-                };
-
-                insert_instruction(InstData::new(
-                    BType::Int,
-                    IRObj::InstId(CONTEXT_STACK.with(|stack| {
-                        stack.borrow().get_current_dfg().borrow().get_next_inst_id()
-                    })),
-                    koopa_op,
-                    vec![
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(left),
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(right),
-                    ],
-                ))
-            }
+impl Drop for Expr {
+    // dropping a long chain would recurse as deep as it nests, so the operands are
+    // moved onto a worklist, and each of them is dropped with only numbers left inside
+    fn drop(&mut self) {
+        let mut operands = vec![];
+        self.take_operands(&mut operands);
+        while let Some(mut operand) = operands.pop() {
+            operand.take_operands(&mut operands);
         }
     }
+}
 
-    fn parse_const_exp(&self) -> IRObj {
+impl Expr {
+    /// move the boxed operands out, leaving numbers in their place.
+    fn take_operands(&mut self, operands: &mut Vec<Expr>) {
+        let mut take = |exp: &mut Expr| {
+            let number = Expr::Number { value: 0, span: Span::default(), id: UNNUMBERED };
+            operands.push(std::mem::replace(exp, number));
+        };
         match self {
-            LOrExp::LAndExp { land_exp } => {
-                return land_exp.parse_const_exp();
-            }
-            LOrExp::LOrExp {
-                lor_exp,
-                lor_op,
-                land_exp,
-            } => {
-                let left = lor_exp.parse_const_exp();
-                let right = land_exp.parse_const_exp();
-
-                match (&left, &right) {
-                    (IRObj::Const(l), IRObj::Const(r)) => {
-                        let res = match lor_op {
-                            LOrOp::Or => {
-                                if *l != 0 || *r != 0 {
-                                    1
-                                } else {
-                                    0
-                                }
-                            }
-                        };
-                        IRObj::Const(res)
-                    }
-                    _ => panic!(
-                        "Non-constant in const expression: left={:?}, right={:?}",
-                        left, right
-                    ),
-                }
+            Expr::Binary { lhs, rhs, .. } => {
+                take(lhs);
+                take(rhs);
             }
+            Expr::Unary { exp, .. } => take(exp),
+            _ => {}
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum LAndExp {
-    EqExp {
-        eq_exp: Box<EqExp>,
-    },
-    LAndExp {
-        land_exp: Box<LAndExp>,
-        land_op: LAndOp,
-        eq_exp: Box<EqExp>,
-    },
-}
-
-impl Expression for LAndExp {
-    fn parse_var_exp(&self) -> IRObj {
+impl Spanned for Expr {
+    fn span(&self) -> Span {
         match self {
-            LAndExp::EqExp { eq_exp } => {
-                return eq_exp.parse_var_exp();
-            }
-            LAndExp::LAndExp {
-                land_exp,
-                land_op,
-                eq_exp,
-            } => {
-                let left = land_exp.parse_var_exp();
-                let right = eq_exp.parse_var_exp();
-
-                let koopa_op = match land_op {
-                    LAndOp::And => KoopaOpCode::AND,
-                };
-
-                insert_instruction(InstData::new(
-                    BType::Int,
-                    IRObj::InstId(CONTEXT_STACK.with(|stack| {
-                        stack.borrow().get_current_dfg().borrow().get_next_inst_id()
-                    })),
-                    koopa_op,
-                    vec![
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(left),
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(right),
-                    ],
-                ))
-            }
+            Expr::Binary { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Number { span, .. }
+            | Expr::FloatNumber { span, .. } => *span,
+            Expr::LVal { l_val } => l_val.span,
         }
     }
+}
 
-    fn parse_const_exp(&self) -> IRObj {
+impl Expr {
+    pub fn id(&self) -> NodeId {
         match self {
-            LAndExp::EqExp { eq_exp } => {
-                return eq_exp.parse_const_exp();
-            }
-            LAndExp::LAndExp {
-                land_exp,
-                land_op: _,
-                eq_exp,
-            } => {
-                let left = land_exp.parse_const_exp();
-                let right = eq_exp.parse_const_exp();
-                match (&left, &right) {
-                    (IRObj::Const(l), IRObj::Const(r)) => {
-                        IRObj::Const(if *l != 0 && *r != 0 { 1 } else { 0 })
-                    }
-                    _ => panic!(
-                        "Non-constant in const expression: left={:?}, right={:?}",
-                        left, right
-                    ),
-                }
-            }
+            Expr::Binary { id, .. }
+            | Expr::Unary { id, .. }
+            | Expr::Call { id, .. }
+            | Expr::Number { id, .. }
+            | Expr::FloatNumber { id, .. } => *id,
+            Expr::LVal { l_val } => l_val.id,
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum EqExp {
-    RelExp {
-        rel_exp: Box<RelExp>,
-    },
-    EqExp {
-        eq_exp: Box<EqExp>,
-        eq_op: EqOp,
-        rel_exp: Box<RelExp>,
-    },
-}
-
-impl Expression for EqExp {
-    fn parse_var_exp(&self) -> IRObj {
-        match self {
-            EqExp::RelExp { rel_exp } => {
-                return rel_exp.parse_var_exp();
+impl Expr {
+    /// lower the expression, which is folded if its value is known at compile time.
+    pub fn parse_var_exp(&self, ctx: &mut ContextStack) -> IRObj {
+        // chains like a + b + c nest on the left as deep as they're long, so the
+        // binary operators on the left spine are lowered with a worklist, innermost first
+        let mut spine = vec![];
+        let mut leaf = self;
+        let mut result = loop {
+            if let Some(value) = ctx.get_const_value(leaf.id()) {
+                break IRObj::from(value);
             }
-            EqExp::EqExp {
-                eq_exp,
-                eq_op,
-                rel_exp,
-            } => {
-                let left = eq_exp.parse_var_exp();
-                let right = rel_exp.parse_var_exp();
-
-                let koopa_op = match eq_op {
-                    EqOp::Eq => KoopaOpCode::EQ,
-                    EqOp::Ne => KoopaOpCode::NE,
-                };
-
-                insert_instruction(InstData::new(
-                    BType::Int,
-                    IRObj::InstId(CONTEXT_STACK.with(|stack| {
-                        stack.borrow().get_current_dfg().borrow().get_next_inst_id()
-                    })),
-                    koopa_op,
-                    vec![
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(left),
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(right),
-                    ],
-                ))
+            match leaf {
+                Expr::Binary { op, lhs, rhs, .. } => {
+                    spine.push((op, rhs));
+                    leaf = lhs;
+                }
+                _ => break leaf.parse_runtime_exp(ctx),
             }
+        };
+
+        for (op, rhs) in spine.into_iter().rev() {
+            result = match op {
+                BinaryOp::And => parse_logical_exp(ctx, result, rhs, KoopaOpCode::AND),
+                BinaryOp::Or => parse_logical_exp(ctx, result, rhs, KoopaOpCode::OR),
+                op => {
+                    let right = rhs.parse_var_exp(ctx);
+                    insert_binary(ctx, to_koopa_op(op), result, right)
+                }
+            };
         }
+        result
     }
 
-    fn parse_const_exp(&self) -> IRObj {
+    /// lower an expression other than a binary operator to instructions that evaluate it
+    /// at runtime.
+    fn parse_runtime_exp(&self, ctx: &mut ContextStack) -> IRObj {
         match self {
-            EqExp::RelExp { rel_exp } => {
-                return rel_exp.parse_const_exp();
-            }
-            EqExp::EqExp {
-                eq_exp,
-                eq_op,
-                rel_exp,
-            } => {
-                let left = eq_exp.parse_const_exp();
-                let right = rel_exp.parse_const_exp();
-                match (&left, &right) {
-                    (IRObj::Const(l), IRObj::Const(r)) => {
-                        let res = match eq_op {
-                            EqOp::Eq => *l == *r,
-                            EqOp::Ne => *l != *r,
-                        };
-                        IRObj::Const(if res { 1 } else { 0 })
-                    }
-                    _ => panic!(
-                        "Non-constant in const expression: left={:?}, right={:?}",
-                        left, right
-                    ),
+            Expr::Binary { .. } => unreachable!("binary operators are lowered by parse_var_exp"),
+
+            Expr::Unary { op, exp, .. } => {
+                let parse_result = exp.parse_var_exp(ctx);
+
+                // -x is 0 - x, and !x is 0 == x
+                let zero = IRObj::from(ConstValue::zero(&parse_result.get_type(ctx)));
+                match op {
+                    UnaryOp::Plus => parse_result,
+                    UnaryOp::Minus => insert_binary(ctx, KoopaOpCode::SUB, zero, parse_result),
+                    UnaryOp::Not => insert_binary(ctx, KoopaOpCode::EQ, zero, parse_result),
                 }
             }
+
+            Expr::Call { ident, args, .. } => parse_call(ctx, ident, args),
+
+            Expr::Number { value, .. } => IRObj::Const(*value),
+            Expr::FloatNumber { value, .. } => IRObj::FloatConst(*value),
+
+            Expr::LVal { l_val } => l_val.parse_value(ctx),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum RelExp {
-    AddExp {
-        add_exp: Box<AddExp>,
-    },
-    RelExp {
-        rel_exp: Box<RelExp>,
-        rel_op: RelOp,
-        add_exp: Box<AddExp>,
-    },
-}
+/// lower a call to the function, whose arguments are converted to the types of the params.
+/// kept out of parse_runtime_exp so that deeply nested expressions take small stack frames.
+fn parse_call(ctx: &mut ContextStack, ident: &str, args: &[Expr]) -> IRObj {
+    let func = ctx.get_func(ident)
+        .expect("callee is resolved by semantic analysis");
+
+    let mut operands = vec![Operand::Func(ident.to_string())];
+    for (arg, param) in args.iter().zip(&func.params) {
+        let arg = arg.parse_var_exp(ctx);
+        let arg = convert(ctx, arg, &param.param_type);
+        operands.push(Operand::from_parse_result(arg));
+    }
 
-impl Expression for RelExp {
-    fn parse_var_exp(&self) -> IRObj {
-        match self {
-            RelExp::AddExp { add_exp } => {
-                return add_exp.parse_var_exp();
-            }
-            RelExp::RelExp {
-                rel_exp,
-                rel_op,
-                add_exp,
-            } => {
-                let left = rel_exp.parse_var_exp();
-                let right = add_exp.parse_var_exp();
-
-                let koopa_op = match rel_op {
-                    RelOp::Lt => KoopaOpCode::LT,
-                    RelOp::Gt => KoopaOpCode::GT,
-                    RelOp::Le => KoopaOpCode::LE,
-                    RelOp::Ge => KoopaOpCode::GE,
-                };
-
-                insert_instruction(InstData::new(
-                    BType::Int,
-                    IRObj::InstId(CONTEXT_STACK.with(|stack| {
-                        stack.borrow().get_current_dfg().borrow().get_next_inst_id()
-                    })),
-                    koopa_op,
-                    vec![
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(left),
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(right),
-                    ],
-                ))
-            }
-        }
+    // calling a void function produces no value
+    let ir_obj = match func.func_type {
+        BType::Void => IRObj::None,
+        _ => IRObj::InstId(ctx.get_next_inst_id()),
+    };
+    let result = insert_instruction(ctx, InstData::new(
+        func.func_type.clone(),
+        ir_obj.clone(),
+        KoopaOpCode::CALL,
+        operands,
+    ));
+
+    match ir_obj {
+        IRObj::None => IRObj::None,
+        _ => result,
     }
+}
 
-    fn parse_const_exp(&self) -> IRObj {
-        match self {
-            RelExp::AddExp { add_exp } => {
-                return add_exp.parse_const_exp();
+impl LVal {
+    /// lower the value of the lvalue, loaded from memory unless it's a constant.
+    fn parse_value(&self, ctx: &mut ContextStack) -> IRObj {
+        let symbol = ctx.lookup(self.id);
+        let typ = ctx.get_symbol(self.id).typ;
+
+        match symbol {
+            IRObj::Pointer { name } => {
+                // if it's a variable stored in memory, load first and return inst_id.
+                let load = insert_instruction(ctx, InstData::new(
+                    typ,
+                    IRObj::InstId(ctx.get_next_inst_id()),
+                    KoopaOpCode::LOAD,
+                    vec![Operand::Pointer(name)],
+                ));
+                // located for the warning on uninitialized variables
+                ctx.get_current_func().record_load(
+                    load.get_id(),
+                    self.ident.clone(),
+                    self.span,
+                );
+                load
             }
-            RelExp::RelExp {
-                rel_exp,
-                rel_op,
-                add_exp,
-            } => {
-                let left = rel_exp.parse_const_exp();
-                let right = add_exp.parse_const_exp();
-                match (&left, &right) {
-                    (IRObj::Const(l), IRObj::Const(r)) => {
-                        let res = match rel_op {
-                            RelOp::Lt => *l < *r,
-                            RelOp::Gt => *l > *r,
-                            RelOp::Le => *l <= *r,
-                            RelOp::Ge => *l >= *r,
-                        };
-                        IRObj::Const(if res { 1 } else { 0 })
-                    }
-                    _ => panic!(
-                        "Non-constant in const expression: left={:?}, right={:?}",
-                        left, right
+            // globals are zero-initialized, so they're always ready to load
+            IRObj::GlobalPointer { name } => insert_instruction(ctx, InstData::new(
+                typ,
+                IRObj::InstId(ctx.get_next_inst_id()),
+                KoopaOpCode::LOAD,
+                vec![Operand::GlobalPointer(name)],
+            )),
+            array @ IRObj::Array { .. } => {
+                let (elem_ptr, elem_typ) = self.parse_elem_ptr(ctx, &array);
+                match elem_typ {
+                    // partially indexed array decays to pointer to its first element
+                    BType::Array(elem, _) => insert_elem_ptr(
+                        ctx,
+                        KoopaOpCode::GETELEMPTR,
+                        elem_ptr,
+                        Operand::Const(0),
+                        *elem,
                     ),
+                    _ => insert_instruction(ctx, InstData::new(
+                        elem_typ,
+                        IRObj::InstId(ctx.get_next_inst_id()),
+                        KoopaOpCode::LOAD,
+                        vec![elem_ptr],
+                    )),
                 }
             }
+            value @ (IRObj::Const(_) | IRObj::FloatConst(_)) => value,
+            _ => unreachable!(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum UnaryExp {
-    PrimaryExp {
-        exp: Box<PrimaryExp>,
-    },
-    UnaryExp {
-        unary_op: UnaryOp,
-        unary_exp: Box<UnaryExp>,
-    },
+/// instruction of a binary operator, except the logical ones lowered to control flow.
+fn to_koopa_op(op: &BinaryOp) -> KoopaOpCode {
+    match op {
+        BinaryOp::Mul => KoopaOpCode::MUL,
+        BinaryOp::Div => KoopaOpCode::DIV,
+        BinaryOp::Mod => KoopaOpCode::MOD,
+        BinaryOp::Add => KoopaOpCode::ADD,
+        BinaryOp::Sub => KoopaOpCode::SUB,
+        BinaryOp::Lt => KoopaOpCode::LT,
+        BinaryOp::Gt => KoopaOpCode::GT,
+        BinaryOp::Le => KoopaOpCode::LE,
+        BinaryOp::Ge => KoopaOpCode::GE,
+        BinaryOp::Eq => KoopaOpCode::EQ,
+        BinaryOp::Ne => KoopaOpCode::NE,
+        BinaryOp::And | BinaryOp::Or => unreachable!("logical operators are short-circuited"),
+    }
 }
 
-impl Expression for UnaryExp {
-    fn parse_var_exp(&self) -> IRObj {
-        match self {
-            // handle primary expression
-            UnaryExp::PrimaryExp { exp } => {
-                return exp.parse_var_exp();
-            }
-
-            // handle unary operation
-            UnaryExp::UnaryExp {
-                unary_op,
-                unary_exp,
-            } => {
-                let parse_result = unary_exp.parse_var_exp();
-
-                match unary_op {
-                    UnaryOp::Plus => parse_result,
-                    UnaryOp::Minus | UnaryOp::Not => insert_instruction(InstData::new(
-                        BType::Int,
-                        IRObj::InstId(CONTEXT_STACK.with(|stack| {
-                            stack.borrow().get_current_dfg().borrow().get_next_inst_id()
-                        })),
-                        match unary_op {
-                            UnaryOp::Minus => KoopaOpCode::SUB,
-                            UnaryOp::Not => KoopaOpCode::EQ,
-                            _ => unreachable!(),
-                        },
-                        vec![
-                            crate::koopa_ir::koopa_ir::Operand::Const(0),
-                            crate::koopa_ir::koopa_ir::Operand::from_parse_result(parse_result),
-                        ],
-                    )),
-                }
-            }
+/// normalize a value to 0/1.
+fn to_bool(ctx: &ContextStack, obj: IRObj) -> IRObj {
+    match obj {
+        IRObj::Const(_) | IRObj::FloatConst(_) => IRObj::Const(obj.get_const().is_true() as i32),
+        _ => {
+            let zero = IRObj::from(ConstValue::zero(&obj.get_type(ctx)));
+            insert_binary(ctx, KoopaOpCode::NE, obj, zero)
         }
     }
+}
 
-    fn parse_const_exp(&self) -> IRObj {
-        match self {
-            UnaryExp::PrimaryExp { exp } => {
-                return exp.parse_const_exp();
-            }
-            UnaryExp::UnaryExp {
-                unary_op,
-                unary_exp,
-            } => {
-                let inner = unary_exp.parse_const_exp();
-                match inner {
-                    IRObj::Const(v) => match unary_op {
-                        UnaryOp::Plus => IRObj::Const(v),
-                        UnaryOp::Minus => IRObj::Const(-v),
-                        UnaryOp::Not => panic!("A const expression couldn't be a NOT expression"),
-                    },
-                    _ => panic!("Non-constant in const expression: {:?}", inner),
-                }
-            }
-        }
+/// branch conditions are ints, so a float condition is compared with 0 first.
+pub fn to_cond(ctx: &ContextStack, obj: IRObj) -> IRObj {
+    match obj.get_type(ctx) {
+        BType::Float => to_bool(ctx, obj),
+        _ => obj,
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum MulExp {
-    UnaryExp {
-        unary_exp: Box<UnaryExp>,
-    },
-    MulExp {
-        mul_exp: Box<MulExp>,
-        mul_op: MulOp,
-        unary_exp: Box<UnaryExp>,
-    },
+/// implicit conversion of a value to typ, e.g. when it's assigned or passed to a function.
+pub fn convert(ctx: &ContextStack, obj: IRObj, typ: &BType) -> IRObj {
+    let opcode = match (obj.get_type(ctx), typ) {
+        (BType::Int, BType::Float) => KoopaOpCode::SITOFP,
+        (BType::Float, BType::Int) => KoopaOpCode::FPTOSI,
+        _ => return obj,
+    };
+    match obj {
+        IRObj::Const(_) | IRObj::FloatConst(_) => IRObj::from(obj.get_const().convert(typ)),
+        _ => insert_instruction(ctx, InstData::new(
+            typ.clone(),
+            IRObj::InstId(ctx.get_next_inst_id()),
+            opcode,
+            vec![Operand::from_parse_result(obj)],
+        )),
+    }
 }
 
-impl Expression for MulExp {
-    fn parse_var_exp(&self) -> IRObj {
-        match self {
-            MulExp::UnaryExp { unary_exp } => {
-                return unary_exp.parse_var_exp();
-            }
-            MulExp::MulExp {
-                mul_exp,
-                mul_op,
-                unary_exp,
-            } => {
-                let left = mul_exp.parse_var_exp();
-                let right = unary_exp.parse_var_exp();
-
-                let koopa_op = match mul_op {
-                    MulOp::Mul => KoopaOpCode::MUL,
-                    MulOp::Div => KoopaOpCode::DIV,
-                    MulOp::Mod => KoopaOpCode::MOD,
-                };
-
-                insert_instruction(InstData::new(
-                    BType::Int,
-                    IRObj::InstId(CONTEXT_STACK.with(|stack| {
-                        stack.borrow().get_current_dfg().borrow().get_next_inst_id()
-                    })),
-                    koopa_op,
-                    vec![
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(left),
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(right),
-                    ],
-                ))
-            }
-        }
-    }
+/// insert an arithmetic or comparison instruction, like C the operands are converted to float
+/// if either of them is float.
+fn insert_binary(ctx: &ContextStack, op: KoopaOpCode, left: IRObj, right: IRObj) -> IRObj {
+    let typ = match (left.get_type(ctx), right.get_type(ctx)) {
+        (BType::Float, _) | (_, BType::Float) => BType::Float,
+        _ => BType::Int,
+    };
+    let left = convert(ctx, left, &typ);
+    let right = convert(ctx, right, &typ);
+
+    // comparisons result in int whatever the operands are
+    let result_typ = match op {
+        KoopaOpCode::ADD | KoopaOpCode::SUB | KoopaOpCode::MUL | KoopaOpCode::DIV | KoopaOpCode::MOD => typ,
+        _ => BType::Int,
+    };
+    insert_instruction(ctx, InstData::new(
+        result_typ,
+        IRObj::InstId(ctx.get_next_inst_id()),
+        op,
+        vec![Operand::from_parse_result(left), Operand::from_parse_result(right)],
+    ))
+}
 
-    fn parse_const_exp(&self) -> IRObj {
-        match self {
-            MulExp::UnaryExp { unary_exp } => {
-                return unary_exp.parse_const_exp();
-            }
-            MulExp::MulExp {
-                mul_exp,
-                mul_op,
-                unary_exp,
-            } => {
-                let left = mul_exp.parse_const_exp();
-                let right = unary_exp.parse_const_exp();
-                match (&left, &right) {
-                    (IRObj::Const(l), IRObj::Const(r)) => {
-                        let res = match mul_op {
-                            MulOp::Mul => *l * *r,
-                            MulOp::Div => *l / *r,
-                            MulOp::Mod => *l % *r,
-                        };
-                        IRObj::Const(res)
-                    }
-                    _ => panic!(
-                        "Non-constant in const expression: left={:?}, right={:?}",
-                        left, right
-                    ),
-                }
-            }
-        }
+/// lower `lhs && rhs` (op is AND) or `lhs || rhs` (op is OR) to control flow, given the
+/// lowered lhs, so that rhs is evaluated only when lhs couldn't decide the result.
+fn parse_logical_exp(ctx: &mut ContextStack, left: IRObj, rhs: &Expr, op: KoopaOpCode) -> IRObj {
+    let is_and = matches!(op, KoopaOpCode::AND);
+    let left = to_bool(ctx, left);
+
+    // constant lhs decides statically whether rhs is evaluated
+    if let IRObj::Const(l) = left {
+        return if (l != 0) == is_and {
+            let right = rhs.parse_var_exp(ctx);
+            to_bool(ctx, right)
+        } else {
+            IRObj::Const(l)
+        };
     }
-}
 
-#[derive(Debug, Clone)]
-pub enum AddExp {
-    MulExp {
-        mul_exp: Box<MulExp>,
-    },
-    AddExp {
-        add_exp: Box<AddExp>,
-        add_op: AddOp,
-        mul_exp: Box<MulExp>,
-    },
+    let func = ctx.get_current_func();
+    let block_id = func.alloc_block_id();
+    let prefix = if is_and { "land" } else { "lor" };
+    let rhs_block = Rc::new(IRBlock::new(format!("{}_rhs_{}", prefix, block_id)));
+    let end_block = Rc::new(IRBlock::new(format!("{}_end_{}", prefix, block_id)));
+
+    // the result is passed through memory, for both paths would reach the end block
+    let name = ctx.alloc_pointer_name(prefix);
+    insert_instruction(ctx, InstData::new(
+        BType::Int,
+        IRObj::Pointer { name: name.clone() },
+        KoopaOpCode::ALLOC,
+        vec![Operand::BType(BType::Int)],
+    ));
+    insert_instruction(ctx, InstData::new(
+        BType::Void,
+        IRObj::None,
+        KoopaOpCode::STORE,
+        vec![Operand::from_parse_result(left.clone()), Operand::Pointer(name.clone())],
+    ));
+
+    let (true_label, false_label) = if is_and {
+        (rhs_block.label.clone(), end_block.label.clone())
+    } else {
+        (end_block.label.clone(), rhs_block.label.clone())
+    };
+    insert_instruction(ctx, InstData::new(
+        BType::Void,
+        IRObj::None,
+        KoopaOpCode::BR,
+        vec![
+            Operand::from_parse_result(left),
+            Operand::Label(true_label),
+            Operand::Label(false_label),
+        ],
+    ));
+
+    ctx.switch_ir_block(rhs_block);
+    let right = rhs.parse_var_exp(ctx);
+    let right = to_bool(ctx, right);
+    insert_instruction(ctx, InstData::new(
+        BType::Void,
+        IRObj::None,
+        KoopaOpCode::STORE,
+        vec![Operand::from_parse_result(right), Operand::Pointer(name.clone())],
+    ));
+    insert_instruction(ctx, InstData::new(
+        BType::Void,
+        IRObj::None,
+        KoopaOpCode::JUMP,
+        vec![Operand::Label(end_block.label.clone())],
+    ));
+
+    ctx.switch_ir_block(end_block);
+    insert_instruction(ctx, InstData::new(
+        BType::Int,
+        IRObj::InstId(ctx.get_next_inst_id()),
+        KoopaOpCode::LOAD,
+        vec![Operand::Pointer(name)],
+    ))
 }
 
-impl Expression for AddExp {
-    fn parse_var_exp(&self) -> IRObj {
-        match self {
-            AddExp::MulExp { mul_exp } => {
-                return mul_exp.parse_var_exp();
-            }
-            AddExp::AddExp {
-                add_exp,
-                add_op,
-                mul_exp,
-            } => {
-                let left = add_exp.parse_var_exp();
-                let right = mul_exp.parse_var_exp();
-
-                let koopa_op = match add_op {
-                    AddOp::Add => KoopaOpCode::ADD,
-                    AddOp::Sub => KoopaOpCode::SUB,
-                };
-
-                insert_instruction(InstData::new(
-                    BType::Int,
-                    IRObj::InstId(CONTEXT_STACK.with(|stack| {
-                        stack.borrow().get_current_dfg().borrow().get_next_inst_id()
-                    })),
-                    koopa_op,
-                    vec![
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(left),
-                        crate::koopa_ir::koopa_ir::Operand::from_parse_result(right),
-                    ],
-                ))
+#[cfg(test)]
+mod tests {
+    use crate::driver::driver::{lower, parse};
+
+    use std::collections::HashMap;
+
+    const F: &str = "int f() { putint(1); return 1; }";
+
+    /// blocks of main in the koopa ir of source, by their labels
+    fn main_blocks(source: &str) -> HashMap<String, Vec<String>> {
+        let (program, _) = lower(&parse(&format!("{}\n{}", F, source)).unwrap()).unwrap();
+        let ir = program.to_string();
+        let main = ir.split("fun @main").nth(1).unwrap();
+        let mut blocks = HashMap::new();
+        let mut label = String::new();
+        for line in main.lines().map(str::trim) {
+            match line.strip_suffix(':') {
+                Some(block) => label = block.to_string(),
+                None if !label.is_empty() && !line.is_empty() && line != "}" => {
+                    blocks.entry(label.clone()).or_insert_with(Vec::new).push(line.to_string())
+                }
+                None => {}
             }
         }
+        blocks
     }
 
-    fn parse_const_exp(&self) -> IRObj {
-        match self {
-            AddExp::MulExp { mul_exp } => {
-                return mul_exp.parse_const_exp();
+    /// whether `call @f` is reached from the block label
+    fn calls_f(blocks: &HashMap<String, Vec<String>>, label: &str) -> bool {
+        let mut stack = vec![label.to_string()];
+        let mut visited = vec![];
+        while let Some(label) = stack.pop() {
+            if visited.contains(&label) {
+                continue;
             }
-            AddExp::AddExp {
-                add_exp,
-                add_op,
-                mul_exp,
-            } => {
-                let left = add_exp.parse_const_exp();
-                let right = mul_exp.parse_const_exp();
-                match (&left, &right) {
-                    (IRObj::Const(l), IRObj::Const(r)) => {
-                        let res = match add_op {
-                            AddOp::Add => *l + *r,
-                            AddOp::Sub => *l - *r,
-                        };
-                        IRObj::Const(res)
-                    }
-                    _ => panic!(
-                        "Non-constant in const expression: left={:?}, right={:?}",
-                        left, right
-                    ),
+            for inst in &blocks[&label] {
+                if inst.contains("call @f(") {
+                    return true;
+                }
+                if let Some(targets) = inst.strip_prefix("br ") {
+                    stack.extend(targets.split(", ").skip(1).map(String::from));
+                } else if let Some(target) = inst.strip_prefix("jump ") {
+                    stack.push(target.to_string());
                 }
             }
+            visited.push(label);
         }
+        false
     }
-}
 
-#[derive(Debug, Clone)]
-pub enum PrimaryExp {
-    Number { value: i32 },
-    LVal { l_val: LVal },
-    Exp { exp: Box<Exp> },
-}
+    /// targets of the branch on the lhs of a logical operator
+    fn branch(blocks: &HashMap<String, Vec<String>>, prefix: &str) -> (String, String) {
+        let br = blocks
+            .values()
+            .flatten()
+            .find(|inst| inst.starts_with("br ") && inst.contains(prefix))
+            .unwrap();
+        let mut targets = br.split(", ").skip(1).map(String::from);
+        (targets.next().unwrap(), targets.next().unwrap())
+    }
 
-impl Expression for PrimaryExp {
-    fn parse_var_exp(&self) -> IRObj {
-        match self {
-            PrimaryExp::Number { value } => IRObj::Const(*value),
-            PrimaryExp::Exp { exp } => exp.parse_var_exp(),
-
-            PrimaryExp::LVal { l_val } => {
-                match CONTEXT_STACK.with(|stack| stack.borrow().find_highest_priority(&l_val.ident))
-                {
-                    Some(IRObj::Pointer {
-                        initialized,
-                        pointer_id,
-                    }) => {
-                        // this case the variables wasn't loaded before use
-                        if !initialized {
-                            panic!("Variable {} used before initialization", l_val.ident);
-                        }
-
-                        // if it's a variable stored in memory, load first and return inst_id.
-                        insert_instruction(InstData::new(
-                            BType::Int,
-                            IRObj::InstId(CONTEXT_STACK.with(|stack| {
-                                stack.borrow().get_current_dfg().borrow().get_next_inst_id()
-                            })),
-                            KoopaOpCode::LOAD,
-                            vec![crate::koopa_ir::koopa_ir::Operand::Pointer(pointer_id)],
-                        ))
-                    }
-                    Some(IRObj::Const(value)) => IRObj::Const(value),
-                    _ => {
-                        panic!("LVal not found in var table, maybe the ident is not defined");
-                    }
-                }
-            }
-        }
+    #[test]
+    fn constant_lhs_skips_rhs() {
+        let source = "int main() { if (0 && f()) return 1; if (1 || f()) return 2; return 0; }";
+        let blocks = main_blocks(source);
+        assert!(!calls_f(&blocks, "%entry"));
     }
 
-    fn parse_const_exp(&self) -> IRObj {
-        match self {
-            PrimaryExp::Number { value } => IRObj::Const(*value),
-            PrimaryExp::Exp { exp } => exp.parse_const_exp(),
-
-            PrimaryExp::LVal { l_val } => {
-                if let Some(value) =
-                    CONTEXT_STACK.with(|stack| stack.borrow().get_latest_const(&l_val.ident))
-                {
-                    value
-                } else {
-                    panic!("LVal not found in const table, maybe the ident is for a variable or not defined");
-                }
-            }
-        }
+    #[test]
+    fn and_skips_rhs_on_false_path() {
+        let blocks = main_blocks("int main() { int a = getint(); if (a && f()) a = 2; return a; }");
+        let (on_true, on_false) = branch(&blocks, "%land_rhs");
+        // not called before the branch either
+        assert!(!blocks["%entry"].iter().any(|inst| inst.contains("call @f(")));
+        assert!(calls_f(&blocks, &on_true));
+        assert!(!calls_f(&blocks, &on_false));
+    }
+
+    #[test]
+    fn or_skips_rhs_on_true_path() {
+        let blocks = main_blocks("int main() { int a = getint(); if (a || f()) a = 2; return a; }");
+        let (on_true, on_false) = branch(&blocks, "%lor_rhs");
+        // not called before the branch either
+        assert!(!blocks["%entry"].iter().any(|inst| inst.contains("call @f(")));
+        assert!(!calls_f(&blocks, &on_true));
+        assert!(calls_f(&blocks, &on_false));
     }
 }
@@ -1,5 +1,10 @@
 pub mod ast;
 pub mod exp;
 pub mod op;
+pub mod const_eval;
 pub mod decl;
+pub mod dump;
+pub mod node_id;
 pub mod stmt;
+pub mod unparse;
+pub mod visit;
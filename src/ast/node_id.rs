@@ -0,0 +1,186 @@
+use crate::ast::ast::{CompUnit, FuncFParam, LVal};
+use crate::ast::decl::{ConstDef, VarDef};
+use crate::ast::exp::Expr;
+use crate::ast::visit::{Visitor, VisitorMut};
+use crate::error::error::{CompileError, ErrorKind};
+use crate::error::span::{Span, Spanned};
+
+use std::collections::HashSet;
+
+/// identity of a node that semantic analysis records facts about, e.g. the symbol
+/// an identifier refers to. unlike spans, ids stay distinct for nodes built by a
+/// rewriting pass.
+pub type NodeId = u32;
+
+/// id of the nodes not numbered yet, e.g. the ones built by the parser
+pub const UNNUMBERED: NodeId = 0;
+
+/// give every node a distinct id. the AST is numbered again after a pass builds or
+/// rewrites nodes, before it's checked.
+pub fn number_nodes(comp_unit: &mut CompUnit) {
+    Numbering { last_id: UNNUMBERED }.visit_comp_unit(comp_unit);
+}
+
+struct Numbering {
+    last_id: NodeId,
+}
+
+impl Numbering {
+    fn next(&mut self) -> NodeId {
+        self.last_id += 1;
+        self.last_id
+    }
+}
+
+impl VisitorMut for Numbering {
+    fn visit_func_f_param(&mut self, node: &mut FuncFParam) {
+        node.id = self.next();
+        self.walk_func_f_param(node)
+    }
+
+    fn visit_const_def(&mut self, node: &mut ConstDef) {
+        node.id = self.next();
+        self.walk_const_def(node)
+    }
+
+    fn visit_var_def(&mut self, node: &mut VarDef) {
+        node.id = self.next();
+        self.walk_var_def(node)
+    }
+
+    fn visit_l_val(&mut self, node: &mut LVal) {
+        node.id = self.next();
+        self.walk_l_val(node)
+    }
+
+    fn enter_expr(&mut self, node: &mut Expr) {
+        match node {
+            Expr::Binary { id, .. }
+            | Expr::Unary { id, .. }
+            | Expr::Call { id, .. }
+            | Expr::Number { id, .. }
+            | Expr::FloatNumber { id, .. } => *id = self.next(),
+            // numbered as an lvalue
+            Expr::LVal { .. } => {}
+        }
+    }
+}
+
+/// check that every node is numbered and no two nodes share an id.
+pub fn check_node_ids(comp_unit: &CompUnit) -> Result<(), CompileError> {
+    let mut checker = IdChecker {
+        seen: HashSet::new(),
+        error: None,
+    };
+    checker.visit_comp_unit(comp_unit);
+    match checker.error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+struct IdChecker {
+    seen: HashSet<NodeId>,
+    // only the first bad node is reported
+    error: Option<CompileError>,
+}
+
+impl IdChecker {
+    fn check(&mut self, id: NodeId, span: Span) {
+        if self.error.is_some() {
+            return;
+        }
+        let problem = if id == UNNUMBERED {
+            "node without id"
+        } else if !self.seen.insert(id) {
+            "node id shared by another node"
+        } else {
+            return;
+        };
+        self.error = Some(
+            ErrorKind::Internal(format!("{}, the AST is numbered again after rewriting it", problem))
+                .at(span),
+        );
+    }
+}
+
+impl Visitor for IdChecker {
+    fn visit_func_f_param(&mut self, node: &FuncFParam) {
+        self.check(node.id, node.span);
+        self.walk_func_f_param(node)
+    }
+
+    fn visit_const_def(&mut self, node: &ConstDef) {
+        self.check(node.id, node.span);
+        self.walk_const_def(node)
+    }
+
+    fn visit_var_def(&mut self, node: &VarDef) {
+        self.check(node.id, node.span);
+        self.walk_var_def(node)
+    }
+
+    fn visit_l_val(&mut self, node: &LVal) {
+        self.check(node.id, node.span);
+        self.walk_l_val(node)
+    }
+
+    fn enter_expr(&mut self, node: &Expr) {
+        if !matches!(node, Expr::LVal { .. }) {
+            self.check(node.id(), node.span());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::ast::{CompUnit, FuncDef, GlobalItem};
+    use crate::driver::driver::{lower, parse};
+    use crate::error::error::ErrorKind;
+
+    use super::number_nodes;
+
+    const SOURCE: &str = "int main() { int a = 1; a = a + 1; return a; }";
+
+    fn first_func(ast: &mut CompUnit) -> &mut FuncDef {
+        match &mut ast.items[0] {
+            GlobalItem::FuncDef(func_def) => func_def,
+            GlobalItem::Decl(_) => unreachable!(),
+        }
+    }
+
+    fn internal_error(ast: &CompUnit) -> bool {
+        match lower(ast) {
+            Ok(_) => false,
+            Err(diagnostics) => diagnostics
+                .errors
+                .iter()
+                .all(|e| matches!(e.kind, ErrorKind::Internal(_))),
+        }
+    }
+
+    #[test]
+    fn cloned_nodes_are_reported() {
+        let mut ast = parse(SOURCE).unwrap();
+        let items = &mut first_func(&mut ast).block.block_items;
+        items.insert(2, items[1].clone());
+        assert!(internal_error(&ast));
+
+        number_nodes(&mut ast);
+        assert!(lower(&ast).is_ok());
+    }
+
+    #[test]
+    fn merged_asts_are_reported() {
+        let mut ast = parse(SOURCE).unwrap();
+        let mut other = parse("int main() { int b = 2; return b; }").unwrap();
+        first_func(&mut other).ident = "f".into();
+        number_nodes(&mut other);
+        ast.items.append(&mut other.items);
+        // ids of the two ASTs overlap
+        assert!(internal_error(&ast));
+
+        number_nodes(&mut ast);
+        assert!(lower(&ast).is_ok());
+    }
+}
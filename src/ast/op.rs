@@ -6,38 +6,60 @@ pub enum UnaryOp {
 }
 
 #[derive(Debug, Clone)]
-pub enum MulOp {
+pub enum BinaryOp {
     Mul,
     Div,
     Mod,
-}
-
-#[derive(Debug, Clone)]
-pub enum AddOp {
     Add,
     Sub,
-}
-
-#[derive(Debug, Clone)]
-pub enum RelOp {
     Lt,
     Gt,
     Le,
     Ge,
-}
-
-#[derive(Debug, Clone)]
-pub enum EqOp {
     Eq,
     Ne,
+    And,
+    Or,
 }
 
-#[derive(Debug, Clone)]
-pub enum LAndOp {
-    And,
+impl UnaryOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnaryOp::Plus => "+",
+            UnaryOp::Minus => "-",
+            UnaryOp::Not => "!",
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum LOrOp {
-    Or,
+impl BinaryOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Le => "<=",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+
+    /// operators of higher precedence bind tighter, all of them are left-associative.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::Ne => 3,
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 6,
+        }
+    }
 }
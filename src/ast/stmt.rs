@@ -1,111 +1,240 @@
 use crate::ast::ast::{Block, LVal};
-use crate::ast::exp::{Exp, Expression, IRObj};
+use crate::ast::exp::{convert, to_cond, Expr, IRObj};
 use crate::config::config::BType;
-use crate::config::config::CONTEXT_STACK;
+use crate::config::config::ContextStack;
+use crate::error::span::{Span, Spanned};
 use crate::koopa_ir::config::KoopaOpCode;
-use crate::koopa_ir::koopa_ir::{insert_instruction, InstData, Operand};
+use crate::koopa_ir::koopa_ir::{insert_instruction, IRBlock, InstData, Operand};
 
 use std::rc::Rc;
 
 pub trait Statement {
-    fn parse(&self);
+    fn parse(&self, ctx: &mut ContextStack);
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Stmt {
-    RegularStmt { l_val: LVal, exp: Exp },
-    RawExp { exp: Option<Exp> },
-    Block { block: Box<Block> },
-    ReturnStmt { exp: Exp },
+    RegularStmt {
+        l_val: LVal,
+        exp: Expr,
+        span: Span,
+    },
+    RawExp {
+        exp: Option<Expr>,
+        span: Span,
+    },
+    Block {
+        block: Box<Block>,
+    },
+    If {
+        cond: Expr,
+        then_stmt: Box<Stmt>,
+        else_stmt: Option<Box<Stmt>>,
+        span: Span,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+        span: Span,
+    },
+    Break {
+        span: Span,
+    },
+    Continue {
+        span: Span,
+    },
+    ReturnStmt {
+        // None for `return;` in void functions
+        exp: Option<Expr>,
+        span: Span,
+    },
+    // statement skipped by the parser for a syntax error
+    Error {
+        span: Span,
+    },
 }
 
-impl Statement for Stmt {
-    fn parse(&self) {
+impl Spanned for Stmt {
+    fn span(&self) -> Span {
         match self {
-            Stmt::RegularStmt { l_val, exp } => {
-                if CONTEXT_STACK
-                    .with(|stack| stack.borrow().get_latest_const(l_val.ident.as_str()))
-                    .is_some()
-                {
-                    panic!("Cannot assign to a constant variable");
-                } else if CONTEXT_STACK
-                    .with(|stack| stack.borrow().get_latest_pointer(l_val.ident.as_str()))
-                    .is_none()
-                {
-                    panic!("Variable {} not declared", l_val.ident);
-                }
+            Stmt::Block { block } => block.span,
+            Stmt::RegularStmt { span, .. }
+            | Stmt::RawExp { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::Break { span }
+            | Stmt::Continue { span }
+            | Stmt::ReturnStmt { span, .. }
+            | Stmt::Error { span } => *span,
+        }
+    }
+}
 
-                let pointer = CONTEXT_STACK
-                    .with(|stack| stack.borrow().get_latest_pointer(l_val.ident.as_str()))
-                    .unwrap();
-                let result = exp.parse_var_exp();
+impl Statement for Stmt {
+    fn parse(&self, ctx: &mut ContextStack) {
+        match self {
+            Stmt::RegularStmt { l_val, exp, .. } => {
+                let pointer = ctx.lookup(l_val.id);
+                let typ = ctx.get_symbol(l_val.id).typ;
+                let (dest, typ) = match pointer {
+                    IRObj::Pointer { name } if l_val.indices.is_empty() => {
+                        (Operand::Pointer(name), typ)
+                    }
+                    IRObj::GlobalPointer { name } if l_val.indices.is_empty() => {
+                        (Operand::GlobalPointer(name), typ)
+                    }
+                    IRObj::Array { .. } => l_val.parse_elem_ptr(ctx, &pointer),
+                    _ => unreachable!("only scalar elements are assigned"),
+                };
+                // the value is converted to the type of the destination
+                let result = exp.parse_var_exp(ctx);
+                let result = convert(ctx, result, &typ);
 
-                insert_instruction(InstData::new(
-                    BType::Void, // assuming integer type for simplicity
+                insert_instruction(ctx, InstData::new(
+                    BType::Void,
                     IRObj::None,
                     KoopaOpCode::STORE,
-                    vec![
-                        match result {
-                            IRObj::InstId(id) => Operand::InstId(id),
-                            IRObj::Const(value) => Operand::Const(value),
-                            IRObj::Pointer {
-                                initialized: _,
-                                pointer_id: _,
-                            } => unimplemented!(),
-                            IRObj::None => panic!("Cannot store void value"),
-                        },
-                        match pointer {
-                            IRObj::Pointer {
-                                initialized: _,
-                                pointer_id,
-                            } => Operand::Pointer(pointer_id),
-                            _ => panic!("Expected a pointer for l_val {}", l_val.ident),
-                        },
-                    ],
+                    vec![Operand::from_parse_result(result), dest],
                 ));
-
-                // set_pointer_initialized
-                CONTEXT_STACK.with(|stack| {
-                    stack
-                        .borrow_mut()
-                        .set_pointer_initialized(l_val.ident.as_str())
-                });
             }
 
-            Stmt::ReturnStmt { exp } => {
-                let result = exp.parse_var_exp();
+            Stmt::ReturnStmt { exp, .. } => {
+                let ret_type = ctx.get_current_func().func_type.clone();
+                let result = exp
+                    .as_ref()
+                    .map_or(IRObj::None, |exp| {
+                        let result = exp.parse_var_exp(ctx);
+                        convert(ctx, result, &ret_type)
+                    });
 
-                insert_instruction(InstData::new(
+                insert_instruction(ctx, InstData::new(
                     BType::Void,
                     IRObj::None,
                     KoopaOpCode::RET,
                     vec![match result {
-                        IRObj::InstId(id) => Operand::InstId(id),
-                        IRObj::Const(value) => Operand::Const(value),
-                        IRObj::Pointer {
-                            initialized: _,
-                            pointer_id: _,
-                        } => unimplemented!(),
                         IRObj::None => Operand::None,
+                        result => Operand::from_parse_result(result),
                     }],
                 ));
             }
 
-            // is it necessary?
-            Stmt::RawExp { exp } => {
+            // evaluated for its side effects, e.g. calls, and the result is dropped
+            Stmt::RawExp { exp, .. } => {
                 if let Some(e) = exp {
-                    let _ = e.parse_var_exp();
+                    e.parse_var_exp(ctx);
                 }
             }
 
-            Stmt::Block { block } => {
-                let ir_block = CONTEXT_STACK.with(|stack| {
-                    let stack = stack.borrow();
-                    stack.get_current_ir_block()
-                });
+            Stmt::Block { block } => block.parse(ctx),
 
-                block.parse(Rc::clone(&ir_block));
+            Stmt::If {
+                cond,
+                then_stmt,
+                else_stmt,
+                ..
+            } => {
+                let cond = cond.parse_var_exp(ctx);
+                let cond = to_cond(ctx, cond);
+
+                let func = ctx.get_current_func();
+                let block_id = func.alloc_block_id();
+                let then_block = Rc::new(IRBlock::new(format!("then_{}", block_id)));
+                let else_block = else_stmt
+                    .as_ref()
+                    .map(|_| Rc::new(IRBlock::new(format!("else_{}", block_id))));
+                let end_block = Rc::new(IRBlock::new(format!("end_{}", block_id)));
+
+                insert_instruction(ctx, InstData::new(
+                    BType::Void,
+                    IRObj::None,
+                    KoopaOpCode::BR,
+                    vec![
+                        Operand::from_parse_result(cond),
+                        Operand::Label(then_block.label.clone()),
+                        Operand::Label(else_block.as_ref().unwrap_or(&end_block).label.clone()),
+                    ],
+                ));
+
+                // the end block is only emitted when some branch could reach it
+                let mut end_reachable = else_block.is_none();
+
+                ctx.switch_ir_block(then_block);
+                then_stmt.parse(ctx);
+                end_reachable |= jump_to(ctx, &end_block);
+
+                if let (Some(else_stmt), Some(else_block)) = (else_stmt, else_block) {
+                    ctx.switch_ir_block(else_block);
+                    else_stmt.parse(ctx);
+                    end_reachable |= jump_to(ctx, &end_block);
+                }
+
+                if end_reachable {
+                    ctx.switch_ir_block(end_block);
+                }
             }
+
+            Stmt::While { cond, body, .. } => {
+                let func = ctx.get_current_func();
+                let block_id = func.alloc_block_id();
+                let entry_block = Rc::new(IRBlock::new(format!("while_entry_{}", block_id)));
+                let body_block = Rc::new(IRBlock::new(format!("while_body_{}", block_id)));
+                let end_block = Rc::new(IRBlock::new(format!("while_end_{}", block_id)));
+
+                // the condition is evaluated in its own block, which is the target of continue
+                jump_to(ctx, &entry_block);
+                ctx.switch_ir_block(Rc::clone(&entry_block));
+                let cond = cond.parse_var_exp(ctx);
+                let cond = to_cond(ctx, cond);
+                insert_instruction(ctx, InstData::new(
+                    BType::Void,
+                    IRObj::None,
+                    KoopaOpCode::BR,
+                    vec![
+                        Operand::from_parse_result(cond),
+                        Operand::Label(body_block.label.clone()),
+                        Operand::Label(end_block.label.clone()),
+                    ],
+                ));
+
+                ctx.switch_ir_block(body_block);
+                ctx.enter_loop(Rc::clone(&entry_block), Rc::clone(&end_block));
+                body.parse(ctx);
+                jump_to(ctx, &entry_block);
+                ctx.exit_loop();
+
+                ctx.switch_ir_block(end_block);
+            }
+
+            Stmt::Break { .. } => {
+                let target = ctx.get_current_loop()
+                    .expect("break is checked to be inside a loop");
+                jump_to(ctx, &target.end_block);
+            }
+
+            Stmt::Continue { .. } => {
+                let target = ctx.get_current_loop()
+                    .expect("continue is checked to be inside a loop");
+                jump_to(ctx, &target.entry_block);
+            }
+
+            Stmt::Error { .. } => {}
         }
     }
 }
+
+/// jump from current block to target unless current block has been terminated.
+/// @return whether the jump is inserted
+fn jump_to(ctx: &ContextStack, target: &IRBlock) -> bool {
+    if ctx.is_current_ir_block_terminated() {
+        return false;
+    }
+
+    insert_instruction(ctx, InstData::new(
+        BType::Void,
+        IRObj::None,
+        KoopaOpCode::JUMP,
+        vec![Operand::Label(target.label.clone())],
+    ));
+    true
+}
@@ -0,0 +1,520 @@
+use crate::ast::ast::{Block, BlockItem, CompUnit, FuncDef, FuncFParam, GlobalItem, LVal};
+use crate::ast::decl::{ConstDecl, ConstDef, ConstExp, ConstInitVal, Decl, InitVal, VarDecl, VarDef};
+use crate::ast::exp::Expr;
+use crate::ast::op::UnaryOp;
+use crate::ast::stmt::Stmt;
+
+/// precedence of unary expressions, above all the binary operators
+const UNARY_PRECEDENCE: u8 = 7;
+/// precedence of calls, lvalues and literals, which never need parentheses
+const PRIMARY_PRECEDENCE: u8 = 8;
+
+/// source being printed, with the indentation of the current line
+pub struct Printer {
+    out: String,
+    depth: usize,
+}
+
+impl Printer {
+    fn new() -> Self {
+        Printer {
+            out: String::new(),
+            depth: 0,
+        }
+    }
+
+    fn write(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str("    ");
+        }
+    }
+
+    /// write the nodes separated by commas
+    fn comma<T: Unparse>(&mut self, nodes: &[T]) {
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            node.unparse(self);
+        }
+    }
+
+    /// write `[dim]` for each dimension
+    fn dims<T: Unparse>(&mut self, dims: &[T]) {
+        for dim in dims {
+            self.write("[");
+            dim.unparse(self);
+            self.write("]");
+        }
+    }
+
+    /// write the body of if or while, either as a block on the same line or as
+    /// a statement on the next line. returns true if the line is left open.
+    fn body(&mut self, stmt: &Stmt, before_else: bool) -> bool {
+        match stmt {
+            Stmt::Block { block } => {
+                self.write(" ");
+                block.unparse(self);
+                true
+            }
+            // an if without else would take the else of the enclosing if
+            stmt if before_else && takes_else(stmt) => {
+                self.write(" {\n");
+                self.depth += 1;
+                stmt.unparse(self);
+                self.depth -= 1;
+                self.indent();
+                self.write("}");
+                true
+            }
+            stmt => {
+                self.write("\n");
+                self.depth += 1;
+                stmt.unparse(self);
+                self.depth -= 1;
+                false
+            }
+        }
+    }
+
+    /// write an if statement from the keyword on, with `else if` kept on one line
+    fn if_stmt(&mut self, cond: &Expr, then_stmt: &Stmt, else_stmt: Option<&Stmt>) {
+        self.write("if (");
+        cond.unparse(self);
+        self.write(")");
+        let open = self.body(then_stmt, else_stmt.is_some());
+
+        let else_stmt = match else_stmt {
+            Some(else_stmt) => else_stmt,
+            None => {
+                if open {
+                    self.write("\n");
+                }
+                return;
+            }
+        };
+        if open {
+            self.write(" else");
+        } else {
+            self.indent();
+            self.write("else");
+        }
+        match else_stmt {
+            Stmt::If {
+                cond,
+                then_stmt,
+                else_stmt,
+                ..
+            } => {
+                self.write(" ");
+                self.if_stmt(cond, then_stmt, else_stmt.as_deref());
+            }
+            else_stmt => {
+                if self.body(else_stmt, false) {
+                    self.write("\n");
+                }
+            }
+        }
+    }
+
+    /// write the operand, in parentheses if it binds looser than the operator
+    fn operand(&mut self, exp: &Expr, precedence: u8) {
+        if exp.precedence() < precedence {
+            self.write("(");
+            exp.unparse(self);
+            self.write(")");
+        } else {
+            exp.unparse(self);
+        }
+    }
+}
+
+/// whether the statement ends with an if without else, which the parser pairs with
+/// the next else.
+fn takes_else(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::If { else_stmt: None, .. } => true,
+        Stmt::If {
+            else_stmt: Some(else_stmt),
+            ..
+        } => takes_else(else_stmt),
+        Stmt::While { body, .. } => takes_else(body),
+        _ => false,
+    }
+}
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary { op, .. } => op.precedence(),
+            Expr::Unary { .. } => UNARY_PRECEDENCE,
+            _ => PRIMARY_PRECEDENCE,
+        }
+    }
+}
+
+/// print an AST node as SysY source, formatted canonically with the fewest
+/// parentheses that keep the grouping of the expressions.
+pub trait Unparse {
+    fn unparse(&self, p: &mut Printer);
+
+    fn to_source(&self) -> String {
+        let mut p = Printer::new();
+        self.unparse(&mut p);
+        p.out
+    }
+}
+
+impl<T: Unparse> Unparse for Box<T> {
+    fn unparse(&self, p: &mut Printer) {
+        self.as_ref().unparse(p)
+    }
+}
+
+impl Unparse for CompUnit {
+    // items keep their order, for a name is only visible after its declaration.
+    // functions are set apart from their neighbours by a blank line.
+    fn unparse(&self, p: &mut Printer) {
+        for (i, item) in self.items.iter().enumerate() {
+            let is_func = |item: &GlobalItem| matches!(item, GlobalItem::FuncDef(_));
+            if i > 0 && (is_func(item) || is_func(&self.items[i - 1])) {
+                p.write("\n");
+            }
+            match item {
+                GlobalItem::Decl(decl) => decl.unparse(p),
+                GlobalItem::FuncDef(func_def) => func_def.unparse(p),
+            }
+        }
+    }
+}
+
+impl Unparse for FuncDef {
+    fn unparse(&self, p: &mut Printer) {
+        p.write(&format!("{} {}(", self.func_type.as_str(), self.ident));
+        p.comma(&self.params);
+        p.write(") ");
+        self.block.unparse(p);
+        p.write("\n");
+    }
+}
+
+impl Unparse for FuncFParam {
+    fn unparse(&self, p: &mut Printer) {
+        p.write(&format!("{} {}", self.b_type.as_str(), self.ident));
+        // the first dimension of an array parameter is omitted, i.e. `int a[][3]`
+        if let Some(dims) = &self.dims {
+            p.write("[]");
+            p.dims(dims);
+        }
+    }
+}
+
+impl Unparse for Block {
+    // the closing brace is left for the caller to end the line
+    fn unparse(&self, p: &mut Printer) {
+        if self.block_items.is_empty() {
+            p.write("{}");
+            return;
+        }
+        p.write("{\n");
+        p.depth += 1;
+        for item in &self.block_items {
+            item.unparse(p);
+        }
+        p.depth -= 1;
+        p.indent();
+        p.write("}");
+    }
+}
+
+impl Unparse for BlockItem {
+    fn unparse(&self, p: &mut Printer) {
+        match self {
+            BlockItem::Decl { decl } => decl.unparse(p),
+            BlockItem::Stmt { stmt } => stmt.unparse(p),
+        }
+    }
+}
+
+impl Unparse for Decl {
+    fn unparse(&self, p: &mut Printer) {
+        match self {
+            Decl::ConstDecl { const_decl } => const_decl.unparse(p),
+            Decl::VarDecl { var_decl } => var_decl.unparse(p),
+            // only in an AST with syntax errors, which is rejected before printing
+            Decl::Error { .. } => {
+                p.indent();
+                p.write("/* syntax error */\n");
+            }
+        }
+    }
+}
+
+impl Unparse for ConstDecl {
+    fn unparse(&self, p: &mut Printer) {
+        p.indent();
+        p.write(&format!("const {} ", self.b_type.as_str()));
+        p.comma(&self.const_defs);
+        p.write(";\n");
+    }
+}
+
+impl Unparse for ConstDef {
+    fn unparse(&self, p: &mut Printer) {
+        p.write(&self.ident);
+        p.dims(&self.dims);
+        p.write(" = ");
+        self.const_init_val.unparse(p);
+    }
+}
+
+impl Unparse for ConstInitVal {
+    fn unparse(&self, p: &mut Printer) {
+        match self {
+            ConstInitVal::ConstExp { const_exp } => const_exp.unparse(p),
+            ConstInitVal::List { const_init_vals, .. } => {
+                p.write("{");
+                p.comma(const_init_vals);
+                p.write("}");
+            }
+        }
+    }
+}
+
+impl Unparse for ConstExp {
+    fn unparse(&self, p: &mut Printer) {
+        self.exp.unparse(p)
+    }
+}
+
+impl Unparse for VarDecl {
+    fn unparse(&self, p: &mut Printer) {
+        p.indent();
+        p.write(&format!("{} ", self.b_type.as_str()));
+        p.comma(&self.var_defs);
+        p.write(";\n");
+    }
+}
+
+impl Unparse for VarDef {
+    fn unparse(&self, p: &mut Printer) {
+        p.write(&self.ident);
+        p.dims(&self.dims);
+        if let Some(init_val) = &self.init_val {
+            p.write(" = ");
+            init_val.unparse(p);
+        }
+    }
+}
+
+impl Unparse for InitVal {
+    fn unparse(&self, p: &mut Printer) {
+        match self {
+            InitVal::Exp { exp } => exp.unparse(p),
+            InitVal::List { init_vals, .. } => {
+                p.write("{");
+                p.comma(init_vals);
+                p.write("}");
+            }
+        }
+    }
+}
+
+impl Unparse for Stmt {
+    // a statement takes whole lines, starting at the current indentation
+    fn unparse(&self, p: &mut Printer) {
+        p.indent();
+        match self {
+            Stmt::RegularStmt { l_val, exp, .. } => {
+                l_val.unparse(p);
+                p.write(" = ");
+                exp.unparse(p);
+                p.write(";\n");
+            }
+            Stmt::RawExp { exp, .. } => {
+                if let Some(exp) = exp {
+                    exp.unparse(p);
+                }
+                p.write(";\n");
+            }
+            Stmt::Block { block } => {
+                block.unparse(p);
+                p.write("\n");
+            }
+            Stmt::If {
+                cond,
+                then_stmt,
+                else_stmt,
+                ..
+            } => p.if_stmt(cond, then_stmt, else_stmt.as_deref()),
+            Stmt::While { cond, body, .. } => {
+                p.write("while (");
+                cond.unparse(p);
+                p.write(")");
+                if p.body(body, false) {
+                    p.write("\n");
+                }
+            }
+            Stmt::Break { .. } => p.write("break;\n"),
+            Stmt::Continue { .. } => p.write("continue;\n"),
+            Stmt::ReturnStmt { exp, .. } => {
+                p.write("return");
+                if let Some(exp) = exp {
+                    p.write(" ");
+                    exp.unparse(p);
+                }
+                p.write(";\n");
+            }
+            // only in an AST with syntax errors, which is rejected before printing
+            Stmt::Error { .. } => p.write("/* syntax error */\n"),
+        }
+    }
+}
+
+impl Unparse for LVal {
+    fn unparse(&self, p: &mut Printer) {
+        p.write(&self.ident);
+        p.dims(&self.indices);
+    }
+}
+
+impl Unparse for Expr {
+    fn unparse(&self, p: &mut Printer) {
+        match self {
+            // left-associative, so only a right operand of the same precedence needs parentheses
+            Expr::Binary { op, lhs, rhs, .. } => {
+                p.operand(lhs, op.precedence());
+                p.write(&format!(" {} ", op.as_str()));
+                p.operand(rhs, op.precedence() + 1);
+            }
+            Expr::Unary { op, exp, .. } => {
+                p.write(op.as_str());
+                // `- -x` rather than the decrement `--x` of C
+                if let Expr::Unary { op: inner, .. } = exp.as_ref() {
+                    if !matches!(op, UnaryOp::Not) && inner.as_str() == op.as_str() {
+                        p.write(" ");
+                    }
+                }
+                p.operand(exp, UNARY_PRECEDENCE);
+            }
+            Expr::Call { ident, args, .. } => {
+                p.write(ident);
+                p.write("(");
+                p.comma(args);
+                p.write(")");
+            }
+            // literals are unsigned, e.g. INT_MIN is parsed from 2147483648
+            Expr::Number { value, .. } => p.write(&(*value as u32).to_string()),
+            Expr::FloatNumber { value, .. } => p.write(&float_literal(*value)),
+            Expr::LVal { l_val } => l_val.unparse(p),
+        }
+    }
+}
+
+/// shortest decimal literal that is parsed to the same value.
+fn float_literal(value: f32) -> String {
+    if value.is_infinite() {
+        // a literal beyond the range of f32 is parsed to infinity
+        return "1e39".to_string();
+    }
+    // debug formatting always has a fraction or an exponent, e.g. 1.0 and 1e-7
+    format!("{:?}", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Unparse;
+    use crate::ast::dump::{DumpFormat, ToNode};
+    use crate::driver::driver::parse;
+
+    /// s-expression dump of the AST without the spans, which change with the layout
+    fn shape(source: &str) -> String {
+        let ast = parse(source).unwrap();
+        ast.to_node()
+            .dump(DumpFormat::Sexpr)
+            .split(' ')
+            .filter(|token| !token.contains(".."))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// unparse the source, checking that the output is parsed to the same AST and
+    /// is printed the same again.
+    fn round_trip(source: &str) -> String {
+        let out = parse(source).unwrap().to_source();
+        assert_eq!(shape(source), shape(&out), "output:\n{}", out);
+        assert_eq!(parse(&out).unwrap().to_source(), out);
+        out
+    }
+
+    /// round trip a return statement, giving the printed expression
+    fn exp(exp: &str) -> String {
+        let out = round_trip(&format!("int main() {{ return {}; }}", exp));
+        out.trim()
+            .strip_prefix("int main() {\n    return ")
+            .and_then(|s| s.strip_suffix(";\n}"))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn right_operand_keeps_parentheses() {
+        assert_eq!(exp("a-(b-c)"), "a - (b - c)");
+        assert_eq!(exp("(a-b)-c"), "a - b - c");
+    }
+
+    #[test]
+    fn redundant_parentheses_are_dropped() {
+        assert_eq!(exp("(a*b)%c"), "a * b % c");
+        assert_eq!(exp("(a+b)*c"), "(a + b) * c");
+        assert_eq!(exp("((a))"), "a");
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(exp("- -x"), "- -x");
+        assert_eq!(exp("-(-x)"), "- -x");
+        assert_eq!(exp("!-+1"), "!-+1");
+        assert_eq!(exp("-(a+b)"), "-(a + b)");
+    }
+
+    #[test]
+    fn dangling_else() {
+        // the else belongs to the outer if, so the inner one stays braced
+        let out = round_trip("int main() { if (a) { while (b) if (c) x = 1; } else x = 2; return 0; }");
+        assert!(out.contains("if (a) {\n        while (b)\n            if (c)"), "{}", out);
+        round_trip("int main() { if (a) while (b) if (c) x = 1; else x = 2; return 0; }");
+        round_trip("int main() { if (a) { if (b) x = 1; } else if (c) x = 2; else x = 3; return 0; }");
+    }
+
+    #[test]
+    fn nested_init_lists() {
+        let out = round_trip("int a[2][2][2] = {{1, 2}, {{3}, {}}, 5}; const int b[2] = {1};");
+        assert!(out.contains("int a[2][2][2] = {{1, 2}, {{3}, {}}, 5};"), "{}", out);
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(exp("0x1F + 017"), "31 + 15");
+        round_trip("float f = 0x1.8p1; float g = 1e-3; float h = .5; float i = 1e40;");
+    }
+
+    #[test]
+    fn items_keep_source_order() {
+        let out = round_trip("int f() { return 1; } int a = 1; int b; int main() { return a; }");
+        assert_eq!(
+            out,
+            "int f() {\n    return 1;\n}\n\nint a = 1;\nint b;\n\nint main() {\n    return a;\n}\n"
+        );
+    }
+
+    #[test]
+    fn int_min() {
+        // INT_MIN is the negation of a literal that only fits as unsigned
+        assert_eq!(exp("-2147483648"), "-2147483648");
+    }
+}
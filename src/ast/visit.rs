@@ -0,0 +1,340 @@
+use crate::ast::ast::{Block, BlockItem, CompUnit, FuncDef, FuncFParam, GlobalItem, LVal};
+use crate::ast::decl::{ConstDecl, ConstDef, ConstExp, ConstInitVal, Decl, InitVal, VarDecl, VarDef};
+use crate::ast::exp::Expr;
+use crate::ast::stmt::Stmt;
+
+/// both visitors are generated from the same walks, with `mut` for VisitorMut.
+///
+/// each node has a `visit_*` method to override and a `walk_*` method which visits
+/// the children in source order. an override calls `walk_*` to keep going down.
+///
+/// expressions are the exception, for chains like `a + b + ...` nest as deep as
+/// they're long: `walk_expr` goes through the whole expression with a worklist and
+/// calls `enter_expr` on every node of it, so per-node work overrides `enter_expr`.
+macro_rules! visitor {
+    ($(#[$doc:meta])* $visitor:ident $(, $mut:tt)?) => {
+        $(#[$doc])*
+        pub trait $visitor {
+            fn visit_comp_unit(&mut self, node: &$($mut)? CompUnit) {
+                self.walk_comp_unit(node)
+            }
+
+            fn walk_comp_unit(&mut self, node: &$($mut)? CompUnit) {
+                for item in &$($mut)? node.items {
+                    match item {
+                        GlobalItem::Decl(decl) => self.visit_decl(decl),
+                        GlobalItem::FuncDef(func_def) => self.visit_func_def(func_def),
+                    }
+                }
+            }
+
+            fn visit_func_def(&mut self, node: &$($mut)? FuncDef) {
+                self.walk_func_def(node)
+            }
+
+            fn walk_func_def(&mut self, node: &$($mut)? FuncDef) {
+                for param in &$($mut)? node.params {
+                    self.visit_func_f_param(param);
+                }
+                self.visit_block(&$($mut)? node.block);
+            }
+
+            fn visit_func_f_param(&mut self, node: &$($mut)? FuncFParam) {
+                self.walk_func_f_param(node)
+            }
+
+            fn walk_func_f_param(&mut self, node: &$($mut)? FuncFParam) {
+                if let Some(dims) = &$($mut)? node.dims {
+                    for dim in dims {
+                        self.visit_const_exp(dim);
+                    }
+                }
+            }
+
+            fn visit_block(&mut self, node: &$($mut)? Block) {
+                self.walk_block(node)
+            }
+
+            fn walk_block(&mut self, node: &$($mut)? Block) {
+                for item in &$($mut)? node.block_items {
+                    self.visit_block_item(item);
+                }
+            }
+
+            fn visit_block_item(&mut self, node: &$($mut)? BlockItem) {
+                self.walk_block_item(node)
+            }
+
+            fn walk_block_item(&mut self, node: &$($mut)? BlockItem) {
+                match node {
+                    BlockItem::Decl { decl } => self.visit_decl(decl),
+                    BlockItem::Stmt { stmt } => self.visit_stmt(stmt),
+                }
+            }
+
+            fn visit_decl(&mut self, node: &$($mut)? Decl) {
+                self.walk_decl(node)
+            }
+
+            fn walk_decl(&mut self, node: &$($mut)? Decl) {
+                match node {
+                    Decl::ConstDecl { const_decl } => self.visit_const_decl(const_decl),
+                    Decl::VarDecl { var_decl } => self.visit_var_decl(var_decl),
+                    Decl::Error { .. } => {}
+                }
+            }
+
+            fn visit_const_decl(&mut self, node: &$($mut)? ConstDecl) {
+                self.walk_const_decl(node)
+            }
+
+            fn walk_const_decl(&mut self, node: &$($mut)? ConstDecl) {
+                for const_def in &$($mut)? node.const_defs {
+                    self.visit_const_def(const_def);
+                }
+            }
+
+            fn visit_const_def(&mut self, node: &$($mut)? ConstDef) {
+                self.walk_const_def(node)
+            }
+
+            fn walk_const_def(&mut self, node: &$($mut)? ConstDef) {
+                for dim in &$($mut)? node.dims {
+                    self.visit_const_exp(dim);
+                }
+                self.visit_const_init_val(&$($mut)? node.const_init_val);
+            }
+
+            fn visit_const_init_val(&mut self, node: &$($mut)? ConstInitVal) {
+                self.walk_const_init_val(node)
+            }
+
+            fn walk_const_init_val(&mut self, node: &$($mut)? ConstInitVal) {
+                match node {
+                    ConstInitVal::ConstExp { const_exp } => self.visit_const_exp(const_exp),
+                    ConstInitVal::List { const_init_vals, .. } => {
+                        for const_init_val in const_init_vals {
+                            self.visit_const_init_val(const_init_val);
+                        }
+                    }
+                }
+            }
+
+            fn visit_const_exp(&mut self, node: &$($mut)? ConstExp) {
+                self.walk_const_exp(node)
+            }
+
+            fn walk_const_exp(&mut self, node: &$($mut)? ConstExp) {
+                self.visit_expr(&$($mut)? node.exp);
+            }
+
+            fn visit_var_decl(&mut self, node: &$($mut)? VarDecl) {
+                self.walk_var_decl(node)
+            }
+
+            fn walk_var_decl(&mut self, node: &$($mut)? VarDecl) {
+                for var_def in &$($mut)? node.var_defs {
+                    self.visit_var_def(var_def);
+                }
+            }
+
+            fn visit_var_def(&mut self, node: &$($mut)? VarDef) {
+                self.walk_var_def(node)
+            }
+
+            fn walk_var_def(&mut self, node: &$($mut)? VarDef) {
+                for dim in &$($mut)? node.dims {
+                    self.visit_const_exp(dim);
+                }
+                if let Some(init_val) = &$($mut)? node.init_val {
+                    self.visit_init_val(init_val);
+                }
+            }
+
+            fn visit_init_val(&mut self, node: &$($mut)? InitVal) {
+                self.walk_init_val(node)
+            }
+
+            fn walk_init_val(&mut self, node: &$($mut)? InitVal) {
+                match node {
+                    InitVal::Exp { exp } => self.visit_expr(exp),
+                    InitVal::List { init_vals, .. } => {
+                        for init_val in init_vals {
+                            self.visit_init_val(init_val);
+                        }
+                    }
+                }
+            }
+
+            fn visit_stmt(&mut self, node: &$($mut)? Stmt) {
+                self.walk_stmt(node)
+            }
+
+            fn walk_stmt(&mut self, node: &$($mut)? Stmt) {
+                match node {
+                    Stmt::RegularStmt { l_val, exp, .. } => {
+                        self.visit_l_val(l_val);
+                        self.visit_expr(exp);
+                    }
+                    Stmt::RawExp { exp, .. } | Stmt::ReturnStmt { exp, .. } => {
+                        if let Some(exp) = exp {
+                            self.visit_expr(exp);
+                        }
+                    }
+                    Stmt::Block { block } => self.visit_block(block),
+                    Stmt::If {
+                        cond,
+                        then_stmt,
+                        else_stmt,
+                        ..
+                    } => {
+                        self.visit_expr(cond);
+                        self.visit_stmt(then_stmt);
+                        if let Some(else_stmt) = else_stmt {
+                            self.visit_stmt(else_stmt);
+                        }
+                    }
+                    Stmt::While { cond, body, .. } => {
+                        self.visit_expr(cond);
+                        self.visit_stmt(body);
+                    }
+                    Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Error { .. } => {}
+                }
+            }
+
+            fn visit_l_val(&mut self, node: &$($mut)? LVal) {
+                self.walk_l_val(node)
+            }
+
+            fn walk_l_val(&mut self, node: &$($mut)? LVal) {
+                for index in &$($mut)? node.indices {
+                    self.visit_expr(index);
+                }
+            }
+
+            fn visit_expr(&mut self, node: &$($mut)? Expr) {
+                self.walk_expr(node)
+            }
+
+            fn enter_expr(&mut self, _node: &$($mut)? Expr) {}
+
+            fn walk_expr(&mut self, node: &$($mut)? Expr) {
+                // the operands left to visit, the next one on top
+                let mut stack = vec![node];
+                while let Some(exp) = stack.pop() {
+                    self.enter_expr(exp);
+                    match exp {
+                        Expr::Binary { lhs, rhs, .. } => {
+                            stack.push(rhs);
+                            stack.push(lhs);
+                        }
+                        Expr::Unary { exp, .. } => stack.push(exp),
+                        Expr::Call { args, .. } => stack.extend(args.into_iter().rev()),
+                        Expr::Number { .. } | Expr::FloatNumber { .. } => {}
+                        Expr::LVal { l_val } => self.visit_l_val(l_val),
+                    }
+                }
+            }
+        }
+    };
+}
+
+visitor!(
+    /// read-only traversal of the AST, e.g. for lints and printers
+    Visitor
+);
+
+visitor!(
+    /// traversal of the AST that could rewrite the nodes in place, e.g. for desugaring
+    VisitorMut, mut
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::ast::{FuncFParam, LVal};
+    use crate::ast::decl::{ConstDef, VarDef};
+    use crate::ast::exp::Expr;
+    use crate::ast::unparse::Unparse;
+    use crate::driver::driver::parse;
+
+    use super::{Visitor, VisitorMut};
+
+    // names and numbers, in the order they're visited
+    struct Recorder {
+        seen: Vec<String>,
+    }
+
+    impl Visitor for Recorder {
+        fn visit_func_f_param(&mut self, node: &FuncFParam) {
+            self.seen.push(node.ident.clone());
+            self.walk_func_f_param(node)
+        }
+
+        fn visit_const_def(&mut self, node: &ConstDef) {
+            self.seen.push(node.ident.clone());
+            self.walk_const_def(node)
+        }
+
+        fn visit_var_def(&mut self, node: &VarDef) {
+            self.seen.push(node.ident.clone());
+            self.walk_var_def(node)
+        }
+
+        fn visit_l_val(&mut self, node: &LVal) {
+            self.seen.push(node.ident.clone());
+            self.walk_l_val(node)
+        }
+
+        fn enter_expr(&mut self, node: &Expr) {
+            match node {
+                Expr::Number { value, .. } => self.seen.push(value.to_string()),
+                Expr::Call { ident, .. } => self.seen.push(format!("{}()", ident)),
+                _ => {}
+            }
+        }
+    }
+
+    // doubles the numbers and renames the lvalues in place
+    struct Rewriter;
+
+    impl VisitorMut for Rewriter {
+        fn visit_l_val(&mut self, node: &mut LVal) {
+            node.ident = node.ident.to_uppercase();
+            self.walk_l_val(node)
+        }
+
+        fn enter_expr(&mut self, node: &mut Expr) {
+            if let Expr::Number { value, .. } = node {
+                *value *= 2;
+            }
+        }
+    }
+
+    #[test]
+    fn walks_in_source_order() {
+        let ast = parse("
+const int N = 2;
+int g[N][3] = {1};
+int f(int a, int b[][3]) { return a * b[0][a + 4] - -5; }
+int main() { int x = f(6, g) || 7; while (x < 8) x = x + 9; return x; }
+")
+        .unwrap();
+        let mut recorder = Recorder { seen: vec![] };
+        recorder.visit_comp_unit(&ast);
+        let expected = [
+            "N", "2", "g", "N", "3", "1", "a", "b", "3", "a", "b", "0", "a", "4", "5", "x",
+            "f()", "6", "g", "7", "x", "8", "x", "x", "9", "x",
+        ];
+        assert_eq!(recorder.seen, expected);
+    }
+
+    #[test]
+    fn rewrites_persist() {
+        let mut ast = parse("int main() { int a[2] = {1}; a[0] = f(a[1], -3); return a[0]; }").unwrap();
+        Rewriter.visit_comp_unit(&mut ast);
+        assert_eq!(
+            ast.to_source(),
+            "int main() {\n    int a[4] = {2};\n    A[0] = f(A[2], -6);\n    return A[0];\n}\n",
+        );
+    }
+}
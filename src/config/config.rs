@@ -1,29 +1,76 @@
+use crate::ast::const_eval::ConstValue;
 use crate::ast::exp::IRObj;
+use crate::ast::node_id::NodeId;
 use crate::koopa_ir::koopa_ir::{DataFlowGraph, Func, IRBlock, InstId};
+use crate::semantic::semantic::SemanticInfo;
+use crate::semantic::symbol::{Symbol, SymbolId};
 
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 /// type of value
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum BType {
     Int,
+    Float,
     Void,
+    Array(Box<BType>, u32), // element type and length
+    Pointer(Box<BType>),    // pointer to the given type
 }
 
 impl BType {
     pub fn as_str(&self) -> &'static str {
         match self {
             BType::Int => "int",
+            BType::Float => "float",
             BType::Void => "void",
+            BType::Array(..) => "array",
+            BType::Pointer(_) => "pointer",
         }
     }
 
     pub fn size_in_bytes(&self) -> u32 {
         match self {
-            BType::Int => 4,
+            BType::Int | BType::Float => 4,
             BType::Void => 0,
+            BType::Array(elem, len) => elem.size_in_bytes() * len,
+            BType::Pointer(_) => 4,
+        }
+    }
+
+    /// build the type of `base name[d0][d1]...` from its dimensions.
+    pub fn array_of(base: BType, dims: &[u32]) -> BType {
+        dims.iter()
+            .rev()
+            .fold(base, |elem, &len| BType::Array(Box::new(elem), len))
+    }
+
+    /// element type of an array or the pointee of a pointer.
+    pub fn get_elem(&self) -> BType {
+        match self {
+            BType::Array(elem, _) | BType::Pointer(elem) => (**elem).clone(),
+            _ => panic!("Not an array or pointer type: {}", self),
+        }
+    }
+
+    /// type of the scalar elements of an array, or the type itself if it's scalar.
+    pub fn get_base(&self) -> BType {
+        match self {
+            BType::Array(elem, _) => elem.get_base(),
+            typ => typ.clone(),
+        }
+    }
+
+    /// lengths of all the dimensions, empty for scalar types.
+    pub fn get_dims(&self) -> Vec<u32> {
+        match self {
+            BType::Array(elem, len) => {
+                let mut dims = vec![*len];
+                dims.extend(elem.get_dims());
+                dims
+            }
+            _ => vec![],
         }
     }
 }
@@ -32,7 +79,10 @@ impl std::fmt::Display for BType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             BType::Int => write!(f, "i32"),
+            BType::Float => write!(f, "f32"),
             BType::Void => write!(f, "void"),
+            BType::Array(elem, len) => write!(f, "[{}, {}]", elem, len),
+            BType::Pointer(elem) => write!(f, "*{}", elem),
         }
     }
 }
@@ -42,10 +92,6 @@ pub struct Context {
     pub func: Rc<Func>,
     // current block
     pub ir_block: Option<Rc<IRBlock>>,
-    // current function's symbol tables
-    pub global_const_table: HashMap<String, IRObj>,
-    // current function's pointer tables
-    pub global_pointer_table: HashMap<String, IRObj>,
 }
 
 impl Context {
@@ -53,31 +99,85 @@ impl Context {
         Context {
             func: Rc::clone(&func),
             ir_block,
-            global_const_table: HashMap::new(),
-            global_pointer_table: HashMap::new(),
         }
     }
 }
 
+/// jump targets of an enclosing while loop
+#[derive(Clone)]
+pub struct LoopTarget {
+    // target of continue
+    pub entry_block: Rc<IRBlock>,
+    // target of break
+    pub end_block: Rc<IRBlock>,
+}
+
+/// state of lowering a program to koopa ir, passed through the parse methods of the AST
 pub struct ContextStack {
     pub stack: Vec<Context>,
+    // enclosing loops, the innermost one is the last
+    pub loop_stack: Vec<LoopTarget>,
+    // functions are visible to the whole program
+    pub func_table: HashMap<String, Rc<Func>>,
+    // constants and pointers of the symbols lowered so far. symbols are resolved
+    // by semantic analysis, so shadowed names never collide here.
+    pub bindings: HashMap<SymbolId, IRObj>,
+    // functions and global values, which share the namespace with the locals in koopa ir
+    pub global_names: HashSet<String>,
+    // result of semantic analysis on the program being lowered
+    pub semantic_info: SemanticInfo,
 }
 
 impl ContextStack {
-    pub fn new() -> Self {
-        ContextStack { stack: vec![] }
+    pub fn new(semantic_info: SemanticInfo) -> Self {
+        ContextStack {
+            stack: vec![],
+            loop_stack: vec![],
+            func_table: HashMap::new(),
+            bindings: HashMap::new(),
+            global_names: HashSet::new(),
+            semantic_info,
+        }
     }
 
-    pub fn enter_block_scope(&mut self, ir_block: Rc<IRBlock>) {
-        let stack = &mut self.stack;
-        let last_context = stack.last_mut().unwrap();
-        let func = Rc::clone(&last_context.func);
+    /// value of the expression, if it's known at compile time.
+    pub fn get_const_value(&self, node: NodeId) -> Option<ConstValue> {
+        self.semantic_info.value_of(node)
+    }
 
-        if last_context.ir_block.is_none() {
-            last_context.ir_block = Some(ir_block);
-        } else {
-            stack.push(Context::new(func, Some(ir_block)));
-        }
+    // the nodes are checked to be resolved before lowering, see `check_resolved`
+
+    /// symbol declared or referred by node.
+    pub fn get_symbol(&self, node: NodeId) -> Symbol {
+        self.semantic_info
+            .symbol_of(node)
+            .expect("every declaration is resolved by semantic analysis")
+            .clone()
+    }
+
+    /// bind the value of the symbol declared by node.
+    pub fn bind(&mut self, node: NodeId, value: IRObj) {
+        let id = self
+            .semantic_info
+            .resolve(node)
+            .expect("every declaration is resolved by semantic analysis");
+        self.bindings.insert(id, value);
+    }
+
+    /// value bound to the symbol that the identifier refers to.
+    pub fn lookup(&self, node: NodeId) -> IRObj {
+        self.semantic_info
+            .resolve(node)
+            .and_then(|id| self.bindings.get(&id))
+            .expect("identifier is resolved by semantic analysis")
+            .clone()
+    }
+
+    /// make ir_block the insertion point of current function and append it to the function's blocks.
+    pub fn switch_ir_block(&mut self, ir_block: Rc<IRBlock>) {
+        let context = self.stack.last_mut().unwrap();
+        context.func.push_ir_block(Rc::clone(&ir_block));
+        context.ir_block = Some(ir_block);
     }
 
     pub fn enter_func_scope(&mut self, func: Rc<Func>) {
@@ -90,72 +190,43 @@ impl ContextStack {
         stack.pop();
     }
 
-    pub fn insert_const(&mut self, name: String, value: IRObj) {
-        if let Some(current_context) = self.stack.last_mut() {
-            current_context.global_const_table.insert(name, value);
-        } else {
-            panic!("No context available to insert constant");
-        }
+    pub fn insert_func(&mut self, func: Rc<Func>) {
+        self.func_table.insert(func.name.clone(), func);
     }
 
-    pub fn insert_pointer(&mut self, name: String, value: IRObj) {
-        if let Some(current_context) = self.stack.last_mut() {
-            current_context.global_pointer_table.insert(name, value);
-        } else {
-            panic!("No context available to insert pointer");
-        }
+    pub fn get_func(&self, name: &str) -> Option<Rc<Func>> {
+        self.func_table.get(name).cloned()
     }
 
-    pub fn set_pointer_initialized(&mut self, name: &str) {
-        for context in self.stack.iter_mut().rev() {
-            if let Some(IRObj::Pointer {
-                initialized,
-                pointer_id: _,
-            }) = context.global_pointer_table.get_mut(name)
-            {
-                *initialized = true;
-                return;
-            }
-        }
-        panic!("Pointer {} not found to set initialized", name);
+    pub fn reserve_global_name(&mut self, name: String) {
+        self.global_names.insert(name);
     }
 
-    pub fn get_latest_const(&self, name: &str) -> Option<IRObj> {
-        for context in self.stack.iter().rev() {
-            if let Some(value) = context.global_const_table.get(name) {
-                return Some(value.clone());
+    /// name a new alloc of current function after ident, e.g. @x_0 for x.
+    pub fn alloc_pointer_name(&self, ident: &str) -> String {
+        let func = self.get_current_func();
+        loop {
+            let name = func.alloc_var_name(ident);
+            if !self.global_names.contains(&name) && !self.func_table.contains_key(&name) {
+                return name;
             }
         }
-        None
     }
 
-    pub fn get_latest_pointer(&self, name: &str) -> Option<IRObj> {
-        for context in self.stack.iter().rev() {
-            if let Some(value) = context.global_pointer_table.get(name) {
-                return Some(value.clone());
-            }
-        }
-        None
+    pub fn enter_loop(&mut self, entry_block: Rc<IRBlock>, end_block: Rc<IRBlock>) {
+        self.loop_stack.push(LoopTarget {
+            entry_block,
+            end_block,
+        });
     }
 
-    pub fn get_current_const(&self, name: &str) -> Option<IRObj> {
-        let stack = &self.stack;
-        if let Some(current_context) = stack.last() {
-            if let Some(value) = current_context.global_const_table.get(name) {
-                return Some(value.clone());
-            }
-        }
-        None
+    pub fn exit_loop(&mut self) {
+        self.loop_stack.pop();
     }
 
-    pub fn get_current_pointer(&self, name: &str) -> Option<IRObj> {
-        let stack = &self.stack;
-        if let Some(current_context) = stack.last() {
-            if let Some(value) = current_context.global_pointer_table.get(name) {
-                return Some(value.clone());
-            }
-        }
-        None
+    /// the innermost loop, None if we're not inside any loop.
+    pub fn get_current_loop(&self) -> Option<LoopTarget> {
+        self.loop_stack.last().cloned()
     }
 
     pub fn get_current_dfg(&self) -> Rc<RefCell<DataFlowGraph>> {
@@ -167,6 +238,11 @@ impl ContextStack {
         }
     }
 
+    /// id of the instruction to be inserted next into current function.
+    pub fn get_next_inst_id(&self) -> InstId {
+        self.get_current_dfg().borrow().get_next_inst_id()
+    }
+
     pub fn get_current_inst_list(&self) -> Rc<RefCell<Vec<InstId>>> {
         let stack = &self.stack;
         if let Some(current_context) = stack.last() {
@@ -202,20 +278,9 @@ impl ContextStack {
         }
     }
 
-    /// find any type of named IRObj with highest priority in the stack.
-    pub fn find_highest_priority(&self, name: &str) -> Option<IRObj> {
-        for context in self.stack.iter().rev() {
-            if let Some(value) = context.global_pointer_table.get(name) {
-                return Some(value.clone());
-            }
-            if let Some(value) = context.global_const_table.get(name) {
-                return Some(value.clone());
-            }
-        }
-        None
+    pub fn is_current_ir_block_terminated(&self) -> bool {
+        let dfg = self.get_current_dfg();
+        let dfg = dfg.borrow();
+        self.get_current_ir_block().is_terminated(&dfg)
     }
 }
-
-thread_local! {
-    pub static CONTEXT_STACK: RefCell<ContextStack> = RefCell::new(ContextStack::new());
-}
@@ -0,0 +1,148 @@
+use crate::asm::asm::Asm;
+use crate::ast::ast::CompUnit;
+use crate::ast::node_id::number_nodes;
+use crate::error::error::{CompileError, ErrorKind};
+use crate::error::span::SourceMap;
+use crate::error::syntax::from_parse_error;
+use crate::error::warning::CompileWarning;
+use crate::koopa_ir::dataflow::find_uninitialized_uses;
+use crate::koopa_ir::koopa_ir::Program;
+use crate::sysy;
+
+/// last stage the compilation goes through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    // parse only, e.g. to dump or format the AST
+    Ast,
+    // check and lower to koopa ir
+    Koopa,
+    // generate riscv asm from the koopa ir
+    #[default]
+    Riscv,
+}
+
+/// what `compile` does with the source
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub stage: Stage,
+}
+
+/// result of each stage that has run
+pub struct Output {
+    pub ast: CompUnit,
+    pub koopa: Option<Program>,
+    pub asm: Option<Asm>,
+    // warnings of all the stages, in the order they're found
+    pub warnings: Vec<CompileWarning>,
+}
+
+/// errors that reject the program, with the warnings found before them
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    pub errors: Vec<CompileError>,
+    pub warnings: Vec<CompileWarning>,
+}
+
+impl Diagnostics {
+    fn from_errors(errors: Vec<CompileError>) -> Self {
+        Diagnostics {
+            errors,
+            warnings: vec![],
+        }
+    }
+
+    /// render the warnings and errors the way the compiler prints them.
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let mut out = render_warnings(source_map, &self.warnings);
+        for e in &self.errors {
+            let message = format!("error: {}", e);
+            match e.span {
+                Some(span) => out.push_str(&source_map.render(&message, span)),
+                None => out.push_str(&format!("{}: {}", source_map.file, message)),
+            }
+            out.push('\n');
+            if let Some(help) = &e.help {
+                out.push_str(&format!("help: {}\n", help));
+            }
+        }
+        if self.errors.len() > 1 {
+            out.push_str(&format!("{} errors generated\n", self.errors.len()));
+        }
+        out
+    }
+}
+
+/// render the warnings, one message per warning.
+pub fn render_warnings(source_map: &SourceMap, warnings: &[CompileWarning]) -> String {
+    warnings
+        .iter()
+        .map(|w| source_map.render(&format!("warning: {}", w), w.span) + "\n")
+        .collect()
+}
+
+/// compile the source up to the stage in options.
+pub fn compile(source: &str, options: &Options) -> Result<Output, Diagnostics> {
+    let ast = parse(source)?;
+    let mut output = Output {
+        ast,
+        koopa: None,
+        asm: None,
+        warnings: vec![],
+    };
+
+    if options.stage >= Stage::Koopa {
+        let (program, warnings) = lower(&output.ast)?;
+        output.warnings = warnings;
+        output.koopa = Some(program);
+    }
+    if options.stage >= Stage::Riscv {
+        let asm = codegen(output.koopa.as_ref().unwrap()).map_err(|mut diagnostics| {
+            diagnostics.warnings = std::mem::take(&mut output.warnings);
+            diagnostics
+        })?;
+        output.asm = Some(asm);
+    }
+
+    Ok(output)
+}
+
+/// parse the source into an AST with its nodes numbered. all syntax errors are
+/// reported together, including the recovered ones.
+pub fn parse(source: &str) -> Result<CompUnit, Diagnostics> {
+    let mut recovered = vec![];
+    let result = sysy::CompUnitParser::new().parse(&mut recovered, source);
+    let mut errors = recovered
+        .into_iter()
+        .map(|recovery| from_parse_error(recovery.error, source))
+        .collect::<Vec<_>>();
+    match result {
+        Ok(mut ast) if errors.is_empty() => {
+            number_nodes(&mut ast);
+            Ok(ast)
+        }
+        Ok(_) => Err(Diagnostics::from_errors(errors)),
+        Err(e) => {
+            errors.push(from_parse_error(e, source));
+            Err(Diagnostics::from_errors(errors))
+        }
+    }
+}
+
+/// check the AST and lower it to koopa ir. semantic errors are reported together
+/// before any IR is generated.
+pub fn lower(ast: &CompUnit) -> Result<(Program, Vec<CompileWarning>), Diagnostics> {
+    let mut info = crate::semantic::semantic::check(ast)?;
+    let mut warnings = std::mem::take(&mut info.warnings);
+    let program = ast.parse(info).map_err(|errors| Diagnostics {
+        errors,
+        warnings: warnings.clone(),
+    })?;
+    // uninitialized variables are found on the CFG of the generated IR
+    warnings.extend(find_uninitialized_uses(&program));
+    Ok((program, warnings))
+}
+
+/// generate riscv asm from the koopa ir.
+pub fn codegen(program: &Program) -> Result<Asm, Diagnostics> {
+    Asm::from(program).map_err(|e| Diagnostics::from_errors(vec![ErrorKind::Codegen(e.to_string()).into()]))
+}
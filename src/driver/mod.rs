@@ -0,0 +1,4 @@
+/**
+ * Entry points of the compiler, for the binary and other crates
+ */
+pub mod driver;
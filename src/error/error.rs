@@ -0,0 +1,177 @@
+use crate::config::config::BType;
+use crate::error::span::Span;
+
+/// errors that make a program rejected by the compiler
+#[derive(Debug, Clone)]
+pub enum ErrorKind {
+    // the source doesn't match the grammar
+    Syntax(String),
+    // identifier declared twice in the same scope
+    Redeclaration(String),
+    // global variable or constant named the same as a function
+    Redefinition(String),
+    // identifier used without declaration
+    UndeclaredIdent(String),
+    // function that is neither defined nor provided by the runtime library
+    UndeclaredFunc(String),
+    // assignment to a constant
+    AssignToConst(String),
+    // assignment to a whole array
+    AssignToArray(String),
+    // initializer or array length that couldn't be evaluated at compile time
+    NonConstInitializer(String),
+    // integer literal that doesn't fit in 32 bits
+    IntegerOutOfRange(String),
+    // division or modulo by zero in a constant expression
+    DivisionByZero,
+    // initializer list that doesn't fit the declared type
+    InvalidInitializer(String),
+    // array length that isn't positive
+    InvalidArrayDim(i32),
+    // subscript on something other than an array
+    NotAnArray(String),
+    // constant index beyond the length of a constant array
+    IndexOutOfBounds { ident: String, index: i32 },
+    // call with wrong number of arguments
+    ArgumentCount {
+        func: String,
+        expected: usize,
+        found: usize,
+    },
+    // result of a void function used as a value
+    VoidValue,
+    // value of a type other than the one required there
+    MismatchedTypes { expected: BType, found: BType },
+    // `return` with a value in a function returning void
+    ReturnValueInVoidFunc,
+    // `return` without a value in a non-void function
+    MissingReturnValue,
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    // failure of generating asm from the koopa ir
+    Codegen(String),
+    // AST that doesn't agree with what semantic analysis recorded, e.g. nodes built
+    // by a rewriting pass without ids
+    Internal(String),
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::Syntax(msg) => write!(f, "syntax error: {}", msg),
+            ErrorKind::Redeclaration(ident) => write!(f, "redeclaration of `{}`", ident),
+            ErrorKind::Redefinition(ident) => {
+                write!(f, "redefinition of `{}` as a different kind of symbol", ident)
+            }
+            ErrorKind::UndeclaredIdent(ident) => {
+                write!(f, "use of undeclared identifier `{}`", ident)
+            }
+            ErrorKind::UndeclaredFunc(ident) => {
+                write!(f, "call to undeclared function `{}`", ident)
+            }
+            ErrorKind::AssignToConst(ident) => {
+                write!(f, "cannot assign to constant `{}`", ident)
+            }
+            ErrorKind::AssignToArray(ident) => write!(f, "cannot assign to array `{}`", ident),
+            ErrorKind::NonConstInitializer(what) => {
+                write!(f, "{} is not a compile-time constant", what)
+            }
+            ErrorKind::IntegerOutOfRange(literal) => {
+                write!(f, "integer literal `{}` is out of the 32-bit range", literal)
+            }
+            ErrorKind::DivisionByZero => write!(f, "division by zero in constant expression"),
+            ErrorKind::InvalidInitializer(msg) => write!(f, "invalid initializer: {}", msg),
+            ErrorKind::InvalidArrayDim(len) => {
+                write!(f, "array length must be positive, found {}", len)
+            }
+            ErrorKind::NotAnArray(ident) => {
+                write!(f, "subscripted value `{}` is not an array", ident)
+            }
+            ErrorKind::IndexOutOfBounds { ident, index } => {
+                write!(f, "index {} is out of bounds of `{}`", index, ident)
+            }
+            ErrorKind::ArgumentCount {
+                func,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function `{}` takes {} arguments but {} were given",
+                func, expected, found
+            ),
+            ErrorKind::VoidValue => write!(f, "void value is used in an expression"),
+            ErrorKind::MismatchedTypes { expected, found } => write!(
+                f,
+                "mismatched types: expected `{}`, found `{}`",
+                expected, found
+            ),
+            ErrorKind::ReturnValueInVoidFunc => {
+                write!(f, "void function should not return a value")
+            }
+            ErrorKind::MissingReturnValue => {
+                write!(f, "non-void function should return a value")
+            }
+            ErrorKind::BreakOutsideLoop => write!(f, "break statement not within a loop"),
+            ErrorKind::ContinueOutsideLoop => {
+                write!(f, "continue statement not within a loop")
+            }
+            ErrorKind::Codegen(msg) => write!(f, "code generation failed: {}", msg),
+            ErrorKind::Internal(msg) => write!(f, "internal compiler error: {}", msg),
+        }
+    }
+}
+
+impl ErrorKind {
+    pub fn at(self, span: Span) -> CompileError {
+        CompileError {
+            kind: self,
+            span: Some(span),
+            help: None,
+        }
+    }
+}
+
+/// error kind with the location of the offending code
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    pub kind: ErrorKind,
+    // None if the error is raised where the location is unknown
+    pub span: Option<Span>,
+    // suggestion printed after the error
+    pub help: Option<String>,
+}
+
+impl CompileError {
+    /// locate the error at span unless it's already located.
+    pub fn or_at(self, span: Span) -> Self {
+        CompileError {
+            span: self.span.or(Some(span)),
+            ..self
+        }
+    }
+
+    pub fn with_help(self, help: String) -> Self {
+        CompileError {
+            help: Some(help),
+            ..self
+        }
+    }
+}
+
+impl From<ErrorKind> for CompileError {
+    fn from(kind: ErrorKind) -> Self {
+        CompileError {
+            kind,
+            span: None,
+            help: None,
+        }
+    }
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for CompileError {}
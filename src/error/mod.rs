@@ -0,0 +1,7 @@
+/**
+ * Errors reported to users
+ */
+pub mod error;
+pub mod span;
+pub mod syntax;
+pub mod warning;
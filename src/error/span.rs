@@ -0,0 +1,82 @@
+/// byte range [start, end) of the source that a node comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// nodes that know where they are in the source
+pub trait Spanned {
+    fn span(&self) -> Span;
+}
+
+/// line/column map of a source file
+pub struct SourceMap {
+    pub file: String,
+    pub source: String,
+    // byte offset where each line begins
+    line_starts: Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(file: String, source: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceMap {
+            file,
+            source,
+            line_starts,
+        }
+    }
+
+    /// 1-based line and column of a byte offset
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let column = self.source[self.line_starts[line]..offset].chars().count() + 1;
+        (line + 1, column)
+    }
+
+    /// text of the 1-based line, without line break
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.source.len());
+        self.source[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    /// render a message at span as `file:line:col: message`, followed by the line and a caret under span.
+    pub fn render(&self, message: &str, span: Span) -> String {
+        let (line, column) = self.location(span.start);
+        let text = self.line(line);
+        // the caret covers the span as long as it stays on the first line
+        let width = self.source[span.start..span.end.max(span.start)]
+            .lines()
+            .next()
+            .map_or(1, |s| s.chars().count().max(1));
+        let padding = text
+            .chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        format!(
+            "{}:{}:{}: {}\n{}\n{}{}",
+            self.file,
+            line,
+            column,
+            message,
+            text,
+            padding,
+            "^".repeat(width)
+        )
+    }
+}
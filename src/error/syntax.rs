@@ -0,0 +1,158 @@
+use crate::error::error::{CompileError, ErrorKind};
+use crate::error::span::Span;
+
+use lalrpop_util::ParseError;
+
+// at most this many expected tokens are listed in a message
+const MAX_EXPECTED: usize = 6;
+
+/// translate the error reported by lalrpop into a readable one.
+pub fn from_parse_error<T: std::fmt::Display>(
+    e: ParseError<usize, T, CompileError>,
+    source: &str,
+) -> CompileError {
+    match e {
+        ParseError::InvalidToken { location } => {
+            let c = source[location..].chars().next().unwrap_or(' ');
+            ErrorKind::Syntax(format!("invalid character `{}`", c))
+                .at(Span::new(location, location + c.len_utf8()))
+        }
+        ParseError::UnrecognizedEOF { location, expected } => {
+            // only closing the innermost bracket could complete a truncated program
+            let missing = unclosed_bracket(&source[..location]);
+            let expected = expected_names(&expected, missing);
+            let error = ErrorKind::Syntax(format!(
+                "unexpected end of file, {}",
+                fmt_expected(&expected)
+            ))
+            .at(Span::new(location, location));
+            with_missing_hint(error, &expected, missing)
+        }
+        ParseError::UnrecognizedToken {
+            token: (l, token, r),
+            expected,
+        } => {
+            let expected_closer = |closer: &char| {
+                expected
+                    .iter()
+                    .any(|terminal| terminal_name(terminal) == format!("`{}`", closer))
+            };
+            // a bracket inside a statement is closed before the statement ends
+            let missing = match unclosed_bracket(&source[..l]) {
+                Some(closer @ (')' | ']')) if expected_closer(&closer) => Some(closer),
+                _ => Some(';'),
+            };
+            let expected = expected_names(&expected, missing);
+            let error = ErrorKind::Syntax(format!(
+                "unexpected `{}`, {}",
+                token,
+                fmt_expected(&expected)
+            ))
+            .at(Span::new(l, r));
+            with_missing_hint(error, &expected, missing)
+        }
+        ParseError::ExtraToken {
+            token: (l, token, r),
+        } => ErrorKind::Syntax(format!("unexpected `{}` after the end of program", token))
+            .at(Span::new(l, r)),
+        // errors raised in grammar actions are already located
+        ParseError::User { error } => error,
+    }
+}
+
+/// readable names of the terminals lalrpop expects, the likely missing one first.
+fn expected_names(expected: &[String], missing: Option<char>) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for terminal in expected {
+        let name = terminal_name(terminal);
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if let Some(missing) = missing {
+        let missing = format!("`{}`", missing);
+        if let Some(i) = names.iter().position(|name| *name == missing) {
+            let name = names.remove(i);
+            names.insert(0, name);
+        }
+    }
+    names
+}
+
+/// closing bracket of the innermost bracket left open in source.
+fn unclosed_bracket(source: &str) -> Option<char> {
+    let mut stack = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => stack.push(')'),
+            '[' => stack.push(']'),
+            '{' => stack.push('}'),
+            ')' | ']' | '}' => {
+                stack.pop();
+            }
+            // brackets in comments don't count
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|c| *c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => {}
+        }
+    }
+    stack.pop()
+}
+
+/// lalrpop prints string terminals as `"x"` and regex terminals as `r#"x"#`.
+fn terminal_name(terminal: &str) -> String {
+    if let Some(regex) = terminal
+        .strip_prefix("r#\"")
+        .and_then(|t| t.strip_suffix("\"#"))
+    {
+        return match regex {
+            r"[_a-zA-Z][_a-zA-Z0-9]*" => "identifier".to_string(),
+            r"[1-9][0-9]*" | r"0[0-7]*" | r"0[xX][0-9a-fA-F]+" => "integer literal".to_string(),
+            // backslashes are escaped in the names of terminals
+            r"([0-9]*\\.[0-9]+|[0-9]+\\.)([eE][+-]?[0-9]+)?|[0-9]+[eE][+-]?[0-9]+"
+            | r"0[xX]([0-9a-fA-F]*\\.[0-9a-fA-F]+|[0-9a-fA-F]+\\.?)[pP][+-]?[0-9]+" => {
+                "float literal".to_string()
+            }
+            // the rest are single operators escaped in regex
+            _ => format!("`{}`", regex.replace('\\', "")),
+        };
+    }
+    match terminal.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Some(literal) => format!("`{}`", literal),
+        None => terminal.to_string(),
+    }
+}
+
+fn fmt_expected(expected: &[String]) -> String {
+    match expected {
+        [] => "expected nothing more".to_string(),
+        [name] => format!("expected {}", name),
+        _ if expected.len() > MAX_EXPECTED => format!(
+            "expected one of {}, ...",
+            expected[..MAX_EXPECTED].join(", ")
+        ),
+        _ => format!("expected one of {}", expected.join(", ")),
+    }
+}
+
+/// suggest the missing token if the parser could accept it here.
+fn with_missing_hint(error: CompileError, expected: &[String], missing: Option<char>) -> CompileError {
+    match missing {
+        Some(missing) if expected.contains(&format!("`{}`", missing)) => {
+            error.with_help(format!("missing `{}`?", missing))
+        }
+        _ => error,
+    }
+}
@@ -0,0 +1,46 @@
+use crate::error::span::Span;
+
+/// suspicious code that is still compiled
+#[derive(Debug, Clone)]
+pub enum WarningKind {
+    // non-void function that could reach its end without return
+    MissingReturn(String),
+    // local variable read on some path where it's never assigned
+    MaybeUninitialized(String),
+    // division or modulo by a constant zero outside constant expressions
+    DivisionByZero,
+}
+
+impl std::fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WarningKind::MissingReturn(func) => write!(
+                f,
+                "control reaches end of non-void function `{}`, which returns 0",
+                func
+            ),
+            WarningKind::MaybeUninitialized(ident) => {
+                write!(f, "variable `{}` may be used uninitialized", ident)
+            }
+            WarningKind::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl WarningKind {
+    pub fn at(self, span: Span) -> CompileWarning {
+        CompileWarning { kind: self, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileWarning {
+    pub kind: WarningKind,
+    pub span: Span,
+}
+
+impl std::fmt::Display for CompileWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
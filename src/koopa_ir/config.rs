@@ -1,10 +1,3 @@
-use std::cell::RefCell;
-
-thread_local! {
-    // initialize pointer id allocator
-    pub static PTR_ID_ALLOCATOR: RefCell<PointerIdAllocator> = RefCell::new(PointerIdAllocator::new());
-}
-
 #[derive(Debug, Clone)]
 pub enum KoopaOpCode {
     NE,
@@ -24,9 +17,16 @@ pub enum KoopaOpCode {
     SHL,
     SHR,
     SAR, // bitwise shift
+    SITOFP,
+    FPTOSI, // conversion between int and float
     STORE,
     LOAD,
     ALLOC, // store, load & ALLOC
+    GETELEMPTR,
+    GETPTR, // address of array elements
+    BR,
+    JUMP, // branch & jump
+    CALL,
     RET,
 }
 
@@ -50,9 +50,16 @@ impl std::fmt::Display for KoopaOpCode {
             KoopaOpCode::SHL => write!(f, "shl"),
             KoopaOpCode::SHR => write!(f, "shr"),
             KoopaOpCode::SAR => write!(f, "sar"),
+            KoopaOpCode::SITOFP => write!(f, "sitofp"),
+            KoopaOpCode::FPTOSI => write!(f, "fptosi"),
             KoopaOpCode::STORE => write!(f, "store"),
             KoopaOpCode::LOAD => write!(f, "load"),
             KoopaOpCode::ALLOC => write!(f, "alloc"),
+            KoopaOpCode::GETELEMPTR => write!(f, "getelemptr"),
+            KoopaOpCode::GETPTR => write!(f, "getptr"),
+            KoopaOpCode::BR => write!(f, "br"),
+            KoopaOpCode::JUMP => write!(f, "jump"),
+            KoopaOpCode::CALL => write!(f, "call"),
             KoopaOpCode::RET => write!(f, "ret"),
         }
     }
@@ -79,28 +86,22 @@ impl KoopaOpCode {
             | KoopaOpCode::SHL
             | KoopaOpCode::SHR
             | KoopaOpCode::SAR 
+            | KoopaOpCode::SITOFP
+            | KoopaOpCode::FPTOSI
             | KoopaOpCode::LOAD 
-            | KoopaOpCode:: ALLOC => true,
+            | KoopaOpCode:: ALLOC 
+            | KoopaOpCode::GETELEMPTR
+            | KoopaOpCode::GETPTR
+            // unless the callee is a void function
+            | KoopaOpCode::CALL => true,
 
             // These opcodes do not produce a return value
-            KoopaOpCode::STORE | KoopaOpCode::RET => false,
+            KoopaOpCode::STORE | KoopaOpCode::BR | KoopaOpCode::JUMP | KoopaOpCode::RET => false,
         }
     }
-}
-
-#[derive(Debug)]
-pub struct PointerIdAllocator {
-    current_id: u32,
-}
-
-impl PointerIdAllocator {
-    pub fn new() -> Self {
-        PointerIdAllocator { current_id: 0 }
-    }
 
-    pub fn alloc(&mut self) -> u32 {
-        let current_id = self.current_id;
-        self.current_id += 1;
-        current_id
+    /// terminators end a basic block.
+    pub fn is_terminator(&self) -> bool {
+        matches!(self, KoopaOpCode::BR | KoopaOpCode::JUMP | KoopaOpCode::RET)
     }
 }
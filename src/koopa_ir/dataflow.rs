@@ -0,0 +1,171 @@
+use crate::ast::exp::IRObj;
+use crate::config::config::BType;
+use crate::error::warning::{CompileWarning, WarningKind};
+use crate::koopa_ir::config::KoopaOpCode;
+use crate::koopa_ir::koopa_ir::{Func, Operand, Program};
+
+use std::collections::{HashMap, HashSet};
+
+/// find the local variables that may be read before assigned, like C it's not an error.
+pub fn find_uninitialized_uses(program: &Program) -> Vec<CompileWarning> {
+    program
+        .funcs
+        .iter()
+        .flat_map(|func| check_func(func))
+        .collect()
+}
+
+/// definite assignment analysis over the CFG of func: a variable is assigned at the entry
+/// of a block only if it's assigned at the exits of all the predecessors.
+fn check_func(func: &Func) -> Vec<CompileWarning> {
+    let dfg = func.dfg.borrow();
+    let blocks = func.ir_blocks.borrow();
+    let block_index = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| (block.label.clone(), i))
+        .collect::<HashMap<_, _>>();
+
+    // scalar variables only, elements of arrays aren't tracked
+    let vars = dfg
+        .inst_map
+        .values()
+        .filter_map(|inst| match (&inst.opcode, &inst.typ, &inst.ir_obj) {
+            (KoopaOpCode::ALLOC, BType::Int | BType::Float, IRObj::Pointer { name }) => {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    // variables stored in each block, and the predecessors of each block
+    let mut stored = vec![HashSet::new(); blocks.len()];
+    let mut preds = vec![vec![]; blocks.len()];
+    for (i, block) in blocks.iter().enumerate() {
+        for inst in block.inst_list.borrow().iter() {
+            let inst_data = dfg.get_inst(inst).unwrap();
+            match inst_data.opcode {
+                KoopaOpCode::STORE => {
+                    if let Operand::Pointer(name) = &inst_data.operands[1] {
+                        stored[i].insert(name.clone());
+                    }
+                }
+                KoopaOpCode::BR | KoopaOpCode::JUMP => {
+                    for label in inst_data.operands.iter().filter(|op| matches!(op, Operand::Label(_))) {
+                        preds[block_index[&label.get_label()]].push(i);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // start from all assigned except the entry, so that the fixed point is the greatest one.
+    // unreachable blocks have no predecessor and stay all assigned, thus never warned.
+    let mut assigned_in = (0..blocks.len())
+        .map(|i| if i == 0 { HashSet::new() } else { vars.clone() })
+        .collect::<Vec<_>>();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in 1..blocks.len() {
+            let assigned = preds[i]
+                .iter()
+                .map(|&pred| &assigned_in[pred] | &stored[pred])
+                .reduce(|a, b| &a & &b)
+                .unwrap_or_else(|| vars.clone());
+            if assigned != assigned_in[i] {
+                assigned_in[i] = assigned;
+                changed = true;
+            }
+        }
+    }
+
+    // walk through each block again to find the loads before stores
+    let load_sources = func.load_sources.borrow();
+    let mut uses = vec![];
+    for (i, block) in blocks.iter().enumerate() {
+        let mut assigned = assigned_in[i].clone();
+        for inst in block.inst_list.borrow().iter() {
+            let inst_data = dfg.get_inst(inst).unwrap();
+            match (&inst_data.opcode, &inst_data.operands[..]) {
+                (KoopaOpCode::STORE, [_, Operand::Pointer(name)]) => {
+                    assigned.insert(name.clone());
+                }
+                (KoopaOpCode::LOAD, [Operand::Pointer(name)])
+                    if vars.contains(name) && !assigned.contains(name) =>
+                {
+                    if let Some((ident, span)) = load_sources.get(inst) {
+                        uses.push((name.clone(), ident.clone(), *span));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // each variable is warned once, at its first use in source
+    uses.sort_by_key(|(_, _, span)| span.start);
+    let mut warned = HashSet::new();
+    uses.into_iter()
+        .filter(|(name, ..)| warned.insert(name.clone()))
+        .map(|(_, ident, span)| WarningKind::MaybeUninitialized(ident).at(span))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_uninitialized_uses;
+    use crate::driver::driver::parse;
+    use crate::error::warning::WarningKind;
+    use crate::semantic::semantic::check;
+
+    // names of the variables warned, in the order of the source
+    fn warned(body: &str) -> Vec<String> {
+        let source = format!("int main() {{ {} }}", body);
+        let ast = parse(&source).unwrap();
+        let program = ast.parse(check(&ast).unwrap()).unwrap();
+        find_uninitialized_uses(&program)
+            .into_iter()
+            .map(|w| match w.kind {
+                WarningKind::MaybeUninitialized(ident) => ident,
+                kind => panic!("unexpected warning: {}", kind),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn straight_line() {
+        assert_eq!(warned("int a; return a;"), ["a"]);
+        assert!(warned("int a; a = 1; return a;").is_empty());
+        assert!(warned("int a = 1; return a;").is_empty());
+    }
+
+    #[test]
+    fn if_paths() {
+        // assigned on one branch only
+        assert_eq!(warned("int a; if (getint()) a = 1; return a;"), ["a"]);
+        assert_eq!(warned("int a; if (getint()) a = 1; else {} return a;"), ["a"]);
+        // assigned on both branches
+        assert!(warned("int a; if (getint()) a = 1; else a = 2; return a;").is_empty());
+        // read inside the branch that assigns it
+        assert!(warned("int a; if (getint()) { a = 1; putint(a); } return 0;").is_empty());
+    }
+
+    #[test]
+    fn while_paths() {
+        // the body may never run
+        assert_eq!(warned("int a; while (getint()) a = 1; return a;"), ["a"]);
+        // read in the body before the assignment of the previous iteration
+        let body = "int a; int i = 0; while (i < 2) { putint(a); a = i; i = i + 1; } return 0;";
+        assert_eq!(warned(body), ["a"]);
+        assert!(warned("int a = 0; while (getint()) a = a + 1; return a;").is_empty());
+        // conditions aren't evaluated, so the loop may be left without the break
+        assert_eq!(warned("int a; while (1) { a = 1; break; } return a;"), ["a"]);
+    }
+
+    #[test]
+    fn warned_once() {
+        assert_eq!(warned("int a, b; putint(a); putint(a); return b;"), ["a", "b"]);
+    }
+}
@@ -1,7 +1,9 @@
-use crate::asm::config::{RVRegCode, RVREG_ALLOCATOR};
+use crate::asm::config::{RVRegAllocator, RVRegCode};
+use crate::ast::const_eval::ConstValue;
 use crate::ast::exp::*;
 use crate::config::config::BType;
-use crate::config::config::CONTEXT_STACK;
+use crate::config::config::ContextStack;
+use crate::error::span::Span;
 use crate::koopa_ir::config::KoopaOpCode;
 
 use std::cell::RefCell;
@@ -10,18 +12,41 @@ use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct Program {
+    // external functions declared with decl, e.g. the runtime library
+    pub func_decls: Vec<Rc<Func>>,
     pub global_vals: Vec<KoopaGlobalVal>,
     pub funcs: Vec<Rc<Func>>,
 }
 
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Program {
     pub fn new() -> Self {
         Self {
+            func_decls: vec![],
             global_vals: vec![],
             funcs: vec![],
         }
     }
 
+    pub fn push_func_decl(&mut self, func: Rc<Func>) {
+        self.func_decls.push(func);
+    }
+
+    /// whether any function defined in this program calls the given function.
+    pub fn is_called(&self, name: &str) -> bool {
+        self.funcs.iter().any(|func| {
+            func.dfg.borrow().inst_map.values().any(|inst| {
+                matches!(inst.opcode, KoopaOpCode::CALL)
+                    && matches!(inst.operands.first(), Some(Operand::Func(callee)) if callee == name)
+            })
+        })
+    }
+
     pub fn push_global_val(&mut self, global_val: KoopaGlobalVal) {
         self.global_vals.push(global_val);
     }
@@ -34,10 +59,22 @@ impl Program {
 // customize formatting for Program
 impl std::fmt::Display for Program {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for func in &self.func_decls {
+            writeln!(f, "{}", func.get_decl_str())?;
+        }
+        if !self.func_decls.is_empty() {
+            writeln!(f)?;
+        }
+
+        for global_val in &self.global_vals {
+            writeln!(f, "{}", global_val)?;
+        }
+        if !self.global_vals.is_empty() {
+            writeln!(f)?;
+        }
+
         for func in &self.funcs {
-            CONTEXT_STACK.with(|stack| stack.borrow_mut().enter_func_scope(Rc::clone(func)));
             writeln!(f, "{}", func)?;
-            CONTEXT_STACK.with(|stack| stack.borrow_mut().exit_scope());
         }
         Ok(())
     }
@@ -48,6 +85,12 @@ pub struct DataFlowGraph {
     pub inst_map: HashMap<InstId, InstData>,
 }
 
+impl Default for DataFlowGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DataFlowGraph {
     pub fn new() -> Self {
         Self {
@@ -71,10 +114,9 @@ impl DataFlowGraph {
         self.inst_map.get(inst_id)
     }
 
-    pub fn free_reg_used(&mut self, inst_id: InstId) {
+    pub fn free_reg_used(&mut self, inst_id: InstId, allocator: &mut RVRegAllocator) {
         if let Some(inst) = self.inst_map.get_mut(&inst_id) {
-            RVREG_ALLOCATOR
-                .with(|allocator| allocator.borrow_mut().free_reg(inst.reg_used.unwrap()));
+            allocator.free_reg(inst.reg_used.unwrap());
 
             inst.free_reg_used();
         } else {
@@ -82,35 +124,31 @@ impl DataFlowGraph {
         }
     }
 
-    pub fn set_reg(&mut self, inst_id: &InstId, reg: Option<RVRegCode>) {
-        if let Some(inst) = self.inst_map.get_mut(&*inst_id) {
+    pub fn set_reg(&mut self, inst_id: &InstId, reg: Option<RVRegCode>, allocator: &mut RVRegAllocator) {
+        if let Some(inst) = self.inst_map.get_mut(inst_id) {
             inst.set_reg(reg.unwrap());
             // concerning that InstData doesn't contain its inst_id, we have to occupy the reg on DFG layer
-            RVREG_ALLOCATOR
-                .with(|allocator| allocator.borrow_mut().occupy_reg(reg.unwrap(), *inst_id));
+            allocator.occupy_reg(reg.unwrap(), *inst_id);
         } else {
             panic!("Instruction not found for inst_id {:?}", inst_id);
         }
     }
 
     pub fn add_user(&mut self, inst_id: &InstId, user_inst_id: InstId) {
-        if let Some(inst) = self.inst_map.get_mut(&*inst_id) {
+        if let Some(inst) = self.inst_map.get_mut(inst_id) {
             inst.add_user(user_inst_id);
         } else {
             panic!("Instruction not found for inst_id {:?}", inst_id);
         }
     }
 
-    pub fn remove_user(&mut self, inst_id: &InstId, user_inst_id: InstId) {
-        if let Some(inst) = self.inst_map.get_mut(&*inst_id) {
+    pub fn remove_user(&mut self, inst_id: &InstId, user_inst_id: InstId, allocator: &mut RVRegAllocator) {
+        if let Some(inst) = self.inst_map.get_mut(inst_id) {
             inst.remove_user(user_inst_id);
 
             // if no users, free the register
-            if inst.users.is_empty() {
-                if let Some(_) = inst.reg_used {
-                    RVREG_ALLOCATOR
-                        .with(|allocator| allocator.borrow_mut().free_reg(inst.reg_used.unwrap()));
-                }
+            if let (true, Some(reg)) = (inst.users.is_empty(), inst.reg_used) {
+                allocator.free_reg(reg);
             }
         } else {
             panic!("Instruction not found for inst_id {:?}", inst_id);
@@ -122,15 +160,43 @@ impl DataFlowGraph {
 pub struct KoopaGlobalVal {
     pub name: String,
     pub val_type: BType,
-    pub val: i32,
+    // flattened initial values, one for each scalar element
+    pub init: Vec<ConstValue>,
 }
 
 impl KoopaGlobalVal {
-    pub fn new(name: String, val_type: BType, val: i32) -> Self {
+    pub fn new(name: String, val_type: BType, init: Vec<ConstValue>) -> Self {
         Self {
             name,
             val_type,
-            val,
+            init,
+        }
+    }
+
+    /// nest the flattened values following the type, e.g. {{1, 2}, {3, 0}}
+    fn fmt_init(typ: &BType, init: &[ConstValue]) -> String {
+        match typ {
+            BType::Array(elem, len) => {
+                let elem_len = init.len() / *len as usize;
+                let elems = init
+                    .chunks(elem_len)
+                    .map(|chunk| Self::fmt_init(elem, chunk))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{{{}}}", elems)
+            }
+            _ => init[0].to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for KoopaGlobalVal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "global @{} = alloc {}, ", self.name, self.val_type)?;
+        if self.init.iter().all(|val| val.to_bits() == 0) {
+            write!(f, "zeroinit")
+        } else {
+            write!(f, "{}", Self::fmt_init(&self.val_type, &self.init))
         }
     }
 }
@@ -142,24 +208,34 @@ pub struct Func {
     pub params: Vec<Param>,
     pub dfg: Rc<RefCell<DataFlowGraph>>,
     pub ir_blocks: Rc<RefCell<Vec<Rc<IRBlock>>>>,
+    // id shared by the blocks of one control flow structure, e.g. %then_0, %else_0, %end_0
+    pub next_block_id: Rc<RefCell<u32>>,
+    // next suffix of the allocs named after each identifier, e.g. @x_0, @x_1
+    pub next_var_suffix: Rc<RefCell<HashMap<String, u32>>>,
+    // variable and its location in source of each load from a local variable
+    pub load_sources: Rc<RefCell<HashMap<InstId, (String, Span)>>>,
 }
 
 impl std::fmt::Display for Func {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(
-            f,
-            "fun @{}({}): {} {{",
-            self.name,
-            self.get_params_str(),
-            self.func_type
-        )?;
+        // the return type of a void function is omitted
+        match self.func_type {
+            BType::Void => writeln!(f, "fun @{}({}) {{", self.name, self.get_params_str())?,
+            _ => writeln!(
+                f,
+                "fun @{}({}): {} {{",
+                self.name,
+                self.get_params_str(),
+                self.func_type
+            )?,
+        }
+        let dfg = self.dfg.borrow();
         for block in &*self.ir_blocks.borrow() {
-            CONTEXT_STACK.with(|stack| stack.borrow_mut().enter_block_scope(Rc::clone(block)));
-            writeln!(f, "{}", block)?;
-            CONTEXT_STACK.with(|stack| stack.borrow_mut().exit_scope());
+            block.write(f, &dfg)?;
+            writeln!(f)?;
         }
 
-        writeln!(f, "}}");
+        writeln!(f, "}}")?;
         Ok(())
     }
 }
@@ -172,20 +248,92 @@ impl Func {
             params,
             dfg: Rc::new(RefCell::new(DataFlowGraph::new())),
             ir_blocks: Rc::new(RefCell::new(vec![])),
+            next_block_id: Rc::new(RefCell::new(0)),
+            next_var_suffix: Rc::new(RefCell::new(HashMap::new())),
+            load_sources: Rc::new(RefCell::new(HashMap::new())),
         }
     }
 
-    pub fn push_ir_block(&mut self, block: Rc<IRBlock>) {
+    pub fn push_ir_block(&self, block: Rc<IRBlock>) {
         self.ir_blocks.borrow_mut().push(Rc::clone(&block));
     }
 
+    /// remember that the load reads the variable ident written at span.
+    pub fn record_load(&self, load: InstId, ident: String, span: Span) {
+        self.load_sources.borrow_mut().insert(load, (ident, span));
+    }
+
+    /// allocate an id for the labels of a new control flow structure.
+    pub fn alloc_block_id(&self) -> u32 {
+        let mut next_block_id = self.next_block_id.borrow_mut();
+        let id = *next_block_id;
+        *next_block_id += 1;
+        id
+    }
+
+    /// name a new alloc after ident, distinct from all the others in this function.
+    pub fn alloc_var_name(&self, ident: &str) -> String {
+        let mut next_var_suffix = self.next_var_suffix.borrow_mut();
+        let suffix = next_var_suffix.entry(ident.to_string()).or_insert(0);
+        let name = format!("{}_{}", ident, suffix);
+        *suffix += 1;
+        name
+    }
+
     pub fn get_params_str(&self) -> String {
         self.params
             .iter()
-            .map(|p| format!("{}: {}", p.name, p.param_type))
+            .map(|p| format!("{}: {}", Operand::Param(p.name.clone()), p.param_type))
             .collect::<Vec<_>>()
             .join(", ")
     }
+
+    /// declaration of an external function, e.g. decl @getint(): i32
+    pub fn get_decl_str(&self) -> String {
+        let params_str = self
+            .params
+            .iter()
+            .map(|p| p.param_type.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.func_type {
+            BType::Void => format!("decl @{}({})", self.name, params_str),
+            _ => format!("decl @{}({}): {}", self.name, params_str, self.func_type),
+        }
+    }
+}
+
+/// functions provided by the SysY runtime library
+pub fn runtime_funcs() -> Vec<Func> {
+    let int_ptr = || BType::Pointer(Box::new(BType::Int));
+    let float_ptr = || BType::Pointer(Box::new(BType::Float));
+    let param = |name: &str, param_type: BType| Param {
+        name: name.to_string(),
+        param_type,
+    };
+
+    vec![
+        Func::new("getint".to_string(), BType::Int, vec![]),
+        Func::new("getch".to_string(), BType::Int, vec![]),
+        Func::new("getarray".to_string(), BType::Int, vec![param("a", int_ptr())]),
+        Func::new("getfloat".to_string(), BType::Float, vec![]),
+        Func::new("getfarray".to_string(), BType::Int, vec![param("a", float_ptr())]),
+        Func::new("putint".to_string(), BType::Void, vec![param("n", BType::Int)]),
+        Func::new("putch".to_string(), BType::Void, vec![param("c", BType::Int)]),
+        Func::new(
+            "putarray".to_string(),
+            BType::Void,
+            vec![param("n", BType::Int), param("a", int_ptr())],
+        ),
+        Func::new("putfloat".to_string(), BType::Void, vec![param("f", BType::Float)]),
+        Func::new(
+            "putfarray".to_string(),
+            BType::Void,
+            vec![param("n", BType::Int), param("a", float_ptr())],
+        ),
+        Func::new("starttime".to_string(), BType::Void, vec![]),
+        Func::new("stoptime".to_string(), BType::Void, vec![]),
+    ]
 }
 
 #[derive(Clone)]
@@ -196,36 +344,48 @@ pub struct Param {
 
 #[derive(Clone)]
 pub struct IRBlock {
+    pub label: String,
     pub inst_list: Rc<RefCell<Vec<InstId>>>,
 }
 
 impl IRBlock {
-    pub fn new() -> Self {
+    pub fn new(label: String) -> Self {
         Self {
+            label,
             inst_list: Rc::new(RefCell::new(vec![])),
         }
     }
-}
 
-impl std::fmt::Display for IRBlock {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "%entry: ")?;
+    /// a block is terminated once its last instruction is br, jump or ret.
+    pub fn is_terminated(&self, dfg: &DataFlowGraph) -> bool {
+        self.inst_list
+            .borrow()
+            .last()
+            .and_then(|inst| dfg.get_inst(inst))
+            .is_some_and(|inst_data| inst_data.opcode.is_terminator())
+    }
+}
 
-        let dfg = CONTEXT_STACK.with(|stack| stack.borrow().get_current_dfg());
-        let dfg_borrow = dfg.borrow();
+impl IRBlock {
+    /// print the block, whose instructions are kept in the dfg of its function.
+    pub fn write(&self, f: &mut std::fmt::Formatter<'_>, dfg: &DataFlowGraph) -> std::fmt::Result {
+        writeln!(f, "%{}:", self.label)?;
 
         for inst in &*self.inst_list.borrow() {
-            let inst_data = dfg_borrow.get_inst(inst).unwrap();
+            let inst_data = dfg.get_inst(inst).unwrap();
             match inst_data.opcode {
                 KoopaOpCode::RET => {
-                    writeln!(f, "  ret {}", inst_data.operands[0].to_string())?;
+                    match &inst_data.operands[0] {
+                        Operand::None => writeln!(f, "  ret")?,
+                        value => writeln!(f, "  ret {}", value)?,
+                    }
                     continue;
                 }
                 _ => {
                     if let IRObj::InstId(_) = inst_data.ir_obj {
                         writeln!(f, "  %{} = {}", inst, inst_data)?;
-                    } else if let IRObj::Pointer { initialized:_, pointer_id } = inst_data.ir_obj {
-                        writeln!(f, "  @{} = {}", pointer_id, inst_data)?;
+                    } else if let IRObj::Pointer { name } = &inst_data.ir_obj {
+                        writeln!(f, "  @{} = {}", name, inst_data)?;
                     } else {
                         writeln!(f, "  {}", inst_data)?;
                     }
@@ -244,8 +404,13 @@ pub type InstId = u32;
 pub enum Operand {
     InstId(InstId), // maybe the operand refers to another instruction's result
     Const(i32),     // maybe the operand is a constant value
+    FloatConst(f32), // float constant, display in hex bits
     BType(BType),   // maybe the operand is a type
-    Pointer(u32),
+    Pointer(String), // local variable, display in format "@name"
+    GlobalPointer(String), // global variable, display in format "@name"
+    Label(String), // target block of br and jump
+    Param(String), // function parameter, display in format "%name"
+    Func(String),  // callee of call
     None,
 }
 
@@ -254,24 +419,43 @@ impl Operand {
         match parse_result {
             IRObj::InstId(id) => Operand::InstId(id),
             IRObj::Const(c) => Operand::Const(c),
-            IRObj::Pointer {
-                initialized: _,
-                pointer_id,
-            } => Operand::Pointer(pointer_id),
-            // None matches to void return
-            IRObj::None => {
-                panic!("Cannot convert IRObj::None to Operand")
-            }
+            IRObj::FloatConst(c) => Operand::FloatConst(c),
+            IRObj::Pointer { name } => Operand::Pointer(name),
+            IRObj::GlobalPointer { name } => Operand::GlobalPointer(name),
+            IRObj::Array { pointer, .. } => Operand::from_parse_result(*pointer),
+            // None matches to void return, which is rejected by semantic analysis
+            IRObj::None => unreachable!("void value used as an operand"),
+        }
+    }
+
+    pub fn get_func(&self) -> String {
+        match self {
+            Operand::Func(name) => name.clone(),
+            _ => panic!("Not a function: {:?}", self),
+        }
+    }
+
+    pub fn get_label(&self) -> String {
+        match self {
+            Operand::Label(label) => label.clone(),
+            _ => panic!("Not a label: {:?}", self),
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Operand::InstId(id) => format!("%{}", id),
-            Operand::Const(c) => format!("{}", c),
-            Operand::BType(b_type) => format!("{}", b_type),
-            Operand::Pointer(pointer) => format!("@{}", pointer),
-            Operand::None => "".to_string(),
+            Operand::InstId(id) => write!(f, "%{}", id),
+            Operand::Const(c) => write!(f, "{}", c),
+            Operand::FloatConst(c) => write!(f, "{}", ConstValue::Float(*c)),
+            Operand::BType(b_type) => write!(f, "{}", b_type),
+            Operand::Pointer(name) => write!(f, "@{}", name),
+            Operand::GlobalPointer(name) => write!(f, "@{}", name),
+            Operand::Label(label) => write!(f, "%{}", label),
+            Operand::Param(name) => write!(f, "%{}", name),
+            Operand::Func(name) => write!(f, "@{}", name),
+            Operand::None => Ok(()),
         }
     }
 }
@@ -287,7 +471,6 @@ pub struct InstData {
 }
 
 impl InstData {
-    // id is either pointer_id or inst_id
     pub fn new(typ: BType, ir_obj: IRObj, opcode: KoopaOpCode, operands: Vec<Operand>) -> Self {
         Self {
             typ,
@@ -320,6 +503,16 @@ impl InstData {
 
 impl std::fmt::Display for InstData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let KoopaOpCode::CALL = self.opcode {
+            // call @func(args)
+            let args_str = self.operands[1..]
+                .iter()
+                .map(|op| op.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return write!(f, "{} {}({})", self.opcode, self.operands[0], args_str);
+        }
+
         let operands_str = self
             .operands
             .iter()
@@ -331,10 +524,10 @@ impl std::fmt::Display for InstData {
     }
 }
 
-pub fn insert_instruction(inst_data: InstData) -> IRObj {
-    let dfg = CONTEXT_STACK.with(|stack| stack.borrow().get_current_dfg());
+pub fn insert_instruction(ctx: &ContextStack, inst_data: InstData) -> IRObj {
+    let dfg = ctx.get_current_dfg();
     let mut dfg_mut = dfg.borrow_mut();
-    let inst_list = CONTEXT_STACK.with(|stack| stack.borrow().get_current_inst_list());
+    let inst_list = ctx.get_current_inst_list();
     let mut inst_list_mut = inst_list.borrow_mut();
 
     let inst_id = dfg_mut.insert_inst(inst_data.clone());
@@ -349,3 +542,13 @@ pub fn insert_instruction(inst_data: InstData) -> IRObj {
     inst_list_mut.push(inst_id);
     IRObj::InstId(inst_id)
 }
+
+/// insert getelemptr or getptr, the result points to elem_typ.
+pub fn insert_elem_ptr(ctx: &ContextStack, opcode: KoopaOpCode, base: Operand, index: Operand, elem_typ: BType) -> IRObj {
+    insert_instruction(ctx, InstData::new(
+        BType::Pointer(Box::new(elem_typ)),
+        IRObj::InstId(ctx.get_next_inst_id()),
+        opcode,
+        vec![base, index],
+    ))
+}
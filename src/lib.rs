@@ -1,5 +1,15 @@
-mod asm;
-mod ast;
-mod koopa_ir;
-mod config;
-mod util;
+use lalrpop_util::lalrpop_mod;
+
+pub mod asm;
+pub mod ast;
+pub mod koopa_ir;
+pub mod semantic;
+pub mod config;
+pub mod driver;
+pub mod error;
+pub mod util;
+
+// parser generated by lalrpop from sysy.lalrpop
+lalrpop_mod!(#[allow(clippy::all)] sysy);
+
+pub use crate::driver::driver::{compile, Diagnostics, Options, Output, Stage};
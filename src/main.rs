@@ -1,23 +1,18 @@
-use clap::Parser;
-use lalrpop_util::lalrpop_mod;
+use clap::{ArgGroup, Parser};
 use std::fs::read_to_string;
 use std::io::Result;
 use std::io::Write;
 
-mod asm;
-mod ast;
-mod config;
-mod koopa_ir;
-mod util;
-use crate::asm::asm::Asm;
-use crate::koopa_ir::koopa_ir::{Program};
-
-// 引用 lalrpop 生成的解析器
-// 因为我们刚刚创建了 sysy.lalrpop, 所以模块名是 sysy
-lalrpop_mod!(sysy);
+use sysy_compiler::ast::dump::{DumpFormat, ToNode};
+use sysy_compiler::ast::unparse::Unparse;
+use sysy_compiler::driver::driver::render_warnings;
+use sysy_compiler::error::span::SourceMap;
+use sysy_compiler::{compile, Options, Stage};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+// each run writes a single result to the output file
+#[command(group(ArgGroup::new("mode").args(["koopa", "riscv", "dump_ast", "fmt"])))]
 struct Cli {
     /// enable this to transform src to koopa ir.
     #[arg(short = 'k', long = "koopa", default_value_t = false)]
@@ -27,6 +22,14 @@ struct Cli {
     #[arg(short = 'r', long = "riscv", default_value_t = false)]
     riscv: bool,
 
+    /// write the AST in the given format.
+    #[arg(long = "dump-ast", value_name = "FORMAT")]
+    dump_ast: Option<DumpFormat>,
+
+    /// write the source formatted canonically.
+    #[arg(short = 'f', long = "fmt", default_value_t = false)]
+    fmt: bool,
+
     /// positional argument for input file.
     #[arg(value_name = "INPUT")]
     input: std::path::PathBuf,
@@ -45,9 +48,7 @@ fn main() -> Result<()> {
                 return a;
             }
             if let Some(s) = a.to_str() {
-                if s.starts_with('-') && !s.starts_with("--") && &s[1..] == "koopa" {
-                    return std::ffi::OsString::from(format!("--{}", &s[1..]));
-                } else if s.starts_with('-') && !s.starts_with("--") && &s[1..] == "riscv" {
+                if s.starts_with('-') && !s.starts_with("--") && matches!(&s[1..], "koopa" | "riscv") {
                     return std::ffi::OsString::from(format!("--{}", &s[1..]));
                 }
             }
@@ -61,56 +62,37 @@ fn main() -> Result<()> {
     let output = cli.output;
 
     // 读取输入文件
-    let input = read_to_string(input)?;
+    let source_map = SourceMap::new(input.display().to_string(), read_to_string(&input)?);
+    let input = &source_map.source;
 
-    // 调用 lalrpop 生成的 parser 解析输入文件
-    let result = sysy::CompUnitParser::new().parse(&input);
-    let ast = match result {
-        Ok(ast_result) => {
-            ast_result
-        }
-        Err(e) => {
-            panic!("Error during parsing: {:?}", e);
-        }
-    };
-
-    let koopa_ir: Option<Program> = if cli.koopa || cli.riscv {
-        // generate Koopa IR
-        Some(ast.parse().unwrap_or_else(|e| {
-            eprintln!("Error during AST to Koopa IR transformation: {:?}", e);
-            Program::new()
-        }))
+    let stage = if cli.riscv {
+        Stage::Riscv
+    } else if cli.koopa {
+        Stage::Koopa
     } else {
-        None
+        Stage::Ast
     };
+    // errors reject the program, and warnings don't stop the compilation
+    let result = compile(input, &Options { stage }).unwrap_or_else(|diagnostics| {
+        eprint!("{}", diagnostics.render(&source_map));
+        std::process::exit(1);
+    });
+    eprint!("{}", render_warnings(&source_map, &result.warnings));
+    let ast = result.ast;
 
-    let asm: Option<Asm> = if cli.riscv {
-        // generate RISC-V asm
-        Some(Asm::from(&koopa_ir.clone().unwrap()).unwrap_or_else(|e| {
-            eprintln!(
-                "Error during Koopa IR to RISC-V assembly transformation: {:?}",
-                e
-            );
-            Asm::new()
-        }))
+    // output the result of the last stage
+    let content = if let Some(format) = cli.dump_ast {
+        ast.to_node().dump(format)
+    } else if cli.fmt {
+        ast.to_source()
+    } else if let Some(asm) = result.asm {
+        format!("{}", asm)
+    } else if let Some(koopa_ir) = result.koopa {
+        format!("{}", koopa_ir)
     } else {
-        None
+        return Ok(());
     };
-
-    // output AST
-    println!("{:#?}", ast);
-    // output the koopa_ir
-    if let Some(koopa_ir) = koopa_ir {
-        let mut f = std::fs::File::create(output.clone())?;
-        // output the koopa ir
-        f.write_all(format!("{}", koopa_ir).as_bytes())?;
-    }
-    // output the asm
-    if let Some(asm) = asm {
-        let mut f = std::fs::File::create(output.clone())?;
-        // output the riscv asm
-        f.write_all(format!("{}", asm).as_bytes())?;
-    }
+    std::fs::File::create(output)?.write_all(content.as_bytes())?;
 
     Ok(())
 }
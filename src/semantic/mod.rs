@@ -0,0 +1,5 @@
+/**
+ * semantic analysis performed on AST before lowering to Koopa IR
+ */
+pub mod semantic;
+pub mod symbol;
@@ -0,0 +1,856 @@
+use crate::ast::ast::{Block, BlockItem, CompUnit, FuncDef, FuncFParam, GlobalItem, LVal};
+use crate::ast::const_eval::ConstValue;
+use crate::ast::decl::{ConstDef, ConstExp, ConstInitVal, Decl, InitList, InitVal, VarDef};
+use crate::ast::exp::*;
+use crate::ast::node_id::{check_node_ids, NodeId};
+use crate::ast::op::{BinaryOp, UnaryOp};
+use crate::ast::stmt::Stmt;
+use crate::ast::visit::Visitor;
+use crate::config::config::BType;
+use crate::driver::driver::Diagnostics;
+use crate::error::error::{CompileError, ErrorKind};
+use crate::error::span::{Span, Spanned};
+use crate::error::warning::{CompileWarning, WarningKind};
+use crate::koopa_ir::koopa_ir::runtime_funcs;
+use crate::semantic::symbol::{FuncSymbol, Symbol, SymbolId, SymbolKind, SymbolTable};
+
+use std::collections::HashMap;
+
+/// what the lowering needs to know about a checked program
+#[derive(Default)]
+pub struct SemanticInfo {
+    pub symbols: Vec<Symbol>,
+    // identifiers and definitions to the symbols they refer to
+    resolutions: HashMap<NodeId, SymbolId>,
+    // expressions whose values are known at compile time
+    values: HashMap<NodeId, ConstValue>,
+    pub warnings: Vec<CompileWarning>,
+}
+
+impl SemanticInfo {
+    pub fn symbol_of(&self, node: NodeId) -> Option<&Symbol> {
+        self.resolutions.get(&node).map(|id| &self.symbols[*id])
+    }
+
+    /// id of the symbol declared or referred by node, distinct for shadowed names.
+    pub fn resolve(&self, node: NodeId) -> Option<SymbolId> {
+        self.resolutions.get(&node).copied()
+    }
+
+    pub fn value_of(&self, node: NodeId) -> Option<ConstValue> {
+        self.values.get(&node).copied()
+    }
+}
+
+/// check the whole program, all the errors found are reported together with the
+/// warnings.
+pub fn check(comp_unit: &CompUnit) -> Result<SemanticInfo, Diagnostics> {
+    // facts are recorded by node id, so every node needs its own
+    check_node_ids(comp_unit).map_err(|e| Diagnostics {
+        errors: vec![e],
+        warnings: vec![],
+    })?;
+
+    let mut checker = Checker::new();
+
+    // the runtime library is visible to the whole program
+    for func in runtime_funcs() {
+        checker
+            .table
+            .declare_func(FuncSymbol {
+                name: func.name.clone(),
+                ret_type: func.func_type.clone(),
+                param_types: func.params.iter().map(|p| p.param_type.clone()).collect(),
+            })
+            .expect("runtime functions have distinct names");
+    }
+
+    // in source order, so that a name is only visible after its declaration
+    for item in &comp_unit.items {
+        match item {
+            GlobalItem::Decl(decl) => checker.check_decl(decl),
+            GlobalItem::FuncDef(func_def) => checker.check_func_def(func_def),
+        }
+    }
+
+    // the ones left are evaluated at runtime
+    for span in std::mem::take(&mut checker.divisions_by_zero) {
+        checker.info.warnings.push(WarningKind::DivisionByZero.at(span));
+    }
+    checker.info.warnings.sort_by_key(|w| w.span.start);
+
+    if !checker.errors.is_empty() {
+        return Err(Diagnostics {
+            errors: checker.errors,
+            warnings: checker.info.warnings,
+        });
+    }
+    checker.info.symbols = checker.table.symbols;
+    Ok(checker.info)
+}
+
+/// whether stmt breaks out of the loop enclosing it.
+fn breaks(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Break { .. } => true,
+        Stmt::Block { block } => block.block_items.iter().any(|item| match item {
+            BlockItem::Decl { .. } => false,
+            BlockItem::Stmt { stmt } => breaks(stmt),
+        }),
+        Stmt::If {
+            then_stmt,
+            else_stmt,
+            ..
+        } => breaks(then_stmt) || else_stmt.as_deref().is_some_and(breaks),
+        // break inside a nested loop leaves that loop only
+        _ => false,
+    }
+}
+
+/// type of a checked expression, together with its value if it's a constant
+struct ExpValue {
+    typ: BType,
+    // the reason why it's not a constant, reported only where a constant is required
+    value: Result<ConstValue, CompileError>,
+}
+
+struct Checker {
+    table: SymbolTable,
+    info: SemanticInfo,
+    errors: Vec<CompileError>,
+    // return type of current function
+    ret_type: BType,
+    // number of enclosing while loops
+    loop_depth: u32,
+    // constant divisions by zero, warned unless they're reported in constant expressions
+    divisions_by_zero: Vec<Span>,
+}
+
+impl Checker {
+    fn new() -> Self {
+        Checker {
+            table: SymbolTable::new(),
+            info: SemanticInfo::default(),
+            errors: vec![],
+            ret_type: BType::Void,
+            loop_depth: 0,
+            divisions_by_zero: vec![],
+        }
+    }
+
+    fn error(&mut self, error: CompileError) {
+        self.errors.push(error);
+    }
+
+    fn declare(&mut self, node: NodeId, symbol: Symbol) -> SymbolId {
+        let span = symbol.decl_span;
+        let id = match self.table.declare(symbol.clone()) {
+            Ok(id) => id,
+            Err(kind) => {
+                self.error(kind.at(span));
+                // keep the duplicated one out of scope, but still record it for the lowering
+                self.table.symbols.push(symbol);
+                self.table.symbols.len() - 1
+            }
+        };
+        self.info.resolutions.insert(node, id);
+        id
+    }
+
+    fn check_func_def(&mut self, func_def: &FuncDef) {
+        let mut param_types = vec![];
+        for param in &func_def.params {
+            param_types.push(match &param.dims {
+                None => param.b_type.clone(),
+                // array parameters are pointers to their first element
+                Some(dims) => BType::Pointer(Box::new(BType::array_of(
+                    param.b_type.clone(),
+                    &self.check_dims(dims),
+                ))),
+            });
+        }
+
+        // declared before checking the body so that it can call itself
+        if let Err(kind) = self.table.declare_func(FuncSymbol {
+            name: func_def.ident.clone(),
+            ret_type: func_def.func_type.clone(),
+            param_types: param_types.clone(),
+        }) {
+            self.error(kind.at(func_def.span));
+        }
+
+        self.ret_type = func_def.func_type.clone();
+        self.table.enter_scope();
+        for (param, typ) in func_def.params.iter().zip(param_types) {
+            self.declare(param.id, Symbol {
+                name: param.ident.clone(),
+                kind: SymbolKind::Param,
+                typ,
+                decl_span: param.span,
+                uses: vec![],
+            });
+        }
+        // params and the outermost block of function body share the same scope
+        self.check_block_items(&func_def.block);
+        self.table.exit_scope();
+
+        // main returns 0 implicitly like C, so it's not warned
+        let block = &func_def.block;
+        if self.ret_type != BType::Void && func_def.ident != "main" && !self.block_returns(block) {
+            // point at the closing brace
+            let span = Span::new(block.span.end - 1, block.span.end);
+            self.info
+                .warnings
+                .push(WarningKind::MissingReturn(func_def.ident.clone()).at(span));
+        }
+    }
+
+    /// whether the control never reaches the end of block.
+    fn block_returns(&self, block: &Block) -> bool {
+        block.block_items.iter().any(|item| match item {
+            BlockItem::Decl { .. } => false,
+            BlockItem::Stmt { stmt } => self.stmt_returns(stmt),
+        })
+    }
+
+    fn stmt_returns(&self, stmt: &Stmt) -> bool {
+        match stmt {
+            Stmt::ReturnStmt { .. } => true,
+            Stmt::Block { block } => self.block_returns(block),
+            Stmt::If {
+                then_stmt,
+                else_stmt: Some(else_stmt),
+                ..
+            } => self.stmt_returns(then_stmt) && self.stmt_returns(else_stmt),
+            // an endless loop never ends unless it breaks
+            Stmt::While { cond, body, .. } => {
+                self.info.value_of(cond.id()).is_some_and(|v| v.is_true()) && !breaks(body)
+            }
+            _ => false,
+        }
+    }
+
+    fn check_block_items(&mut self, block: &Block) {
+        for item in &block.block_items {
+            match item {
+                BlockItem::Decl { decl } => self.check_decl(decl),
+                BlockItem::Stmt { stmt } => self.check_stmt(stmt),
+            }
+        }
+    }
+
+    fn check_decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::ConstDecl { const_decl } => {
+                for const_def in &const_decl.const_defs {
+                    self.check_const_def(&const_decl.b_type, const_def);
+                }
+            }
+            Decl::VarDecl { var_decl } => {
+                for var_def in &var_decl.var_defs {
+                    self.check_var_def(&var_decl.b_type, var_def);
+                }
+            }
+            Decl::Error { .. } => {}
+        }
+    }
+
+    /// lengths of array dimensions, the invalid ones are taken as 1 after reported.
+    fn check_dims(&mut self, dims: &[ConstExp]) -> Vec<u32> {
+        dims.iter()
+            .map(|dim| {
+                // unlike initializers, lengths are never converted from float
+                let value = self.check_exp(&dim.exp);
+                let len = self
+                    .require_int(value, dim.span())
+                    .and_then(|value| self.require_const(value.value));
+                match len.map(ConstValue::as_int) {
+                    Some(len) if len > 0 => len as u32,
+                    Some(len) => {
+                        self.error(ErrorKind::InvalidArrayDim(len).at(dim.span()));
+                        1
+                    }
+                    None => 1,
+                }
+            })
+            .collect()
+    }
+
+    fn check_const_def(&mut self, b_type: &BType, const_def: &ConstDef) {
+        let errors = self.errors.len();
+        let typ = BType::array_of(b_type.clone(), &self.check_dims(&const_def.dims));
+        let zero = ConstValue::zero(b_type);
+
+        let values = match &const_def.const_init_val {
+            ConstInitVal::ConstExp { const_exp } if const_def.dims.is_empty() => {
+                vec![self.check_const_exp(&const_exp.exp).unwrap_or(zero)]
+            }
+            ConstInitVal::List { span, .. } if const_def.dims.is_empty() => {
+                self.error(
+                    ErrorKind::InvalidInitializer(
+                        "scalar constant couldn't be initialized with a list".to_string(),
+                    )
+                    .at(*span),
+                );
+                vec![]
+            }
+            init_val => match init_val.flatten(&typ) {
+                Ok(flat) => flat
+                    .into_iter()
+                    .map(|exp| exp.map_or(Some(zero), |exp| self.check_const_exp(exp)).unwrap_or(zero))
+                    .collect(),
+                Err(e) => {
+                    self.error(e);
+                    vec![]
+                }
+            },
+        };
+
+        // values of a constant with errors are unknown
+        let values = if self.errors.len() > errors {
+            vec![]
+        } else {
+            values.into_iter().map(|value| value.convert(b_type)).collect()
+        };
+        self.declare(const_def.id, Symbol {
+            name: const_def.ident.clone(),
+            kind: SymbolKind::Const(values),
+            typ,
+            decl_span: const_def.span,
+            uses: vec![],
+        });
+    }
+
+    fn check_var_def(&mut self, b_type: &BType, var_def: &VarDef) {
+        let typ = BType::array_of(b_type.clone(), &self.check_dims(&var_def.dims));
+        // initializer of a global variable must be evaluated at compile time
+        let global = self.table.is_global_scope();
+
+        match &var_def.init_val {
+            Some(InitVal::Exp { exp }) if var_def.dims.is_empty() => {
+                self.check_init_exp(exp, global);
+            }
+            Some(InitVal::List { span, .. }) if var_def.dims.is_empty() => {
+                self.error(
+                    ErrorKind::InvalidInitializer(
+                        "scalar variable couldn't be initialized with a list".to_string(),
+                    )
+                    .at(*span),
+                );
+            }
+            Some(init_val) => match init_val.flatten(&typ) {
+                Ok(flat) => {
+                    for exp in flat.into_iter().flatten() {
+                        self.check_init_exp(exp, global);
+                    }
+                }
+                Err(e) => self.error(e),
+            },
+            None => {}
+        }
+
+        self.declare(var_def.id, Symbol {
+            name: var_def.ident.clone(),
+            kind: SymbolKind::Var,
+            typ,
+            decl_span: var_def.span,
+            uses: vec![],
+        });
+    }
+
+    fn check_init_exp(&mut self, exp: &Expr, global: bool) {
+        if global {
+            self.check_const_exp(exp);
+        } else {
+            let value = self.check_exp(exp);
+            self.require_number(value, exp.span());
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::RegularStmt { l_val, exp, .. } => {
+                self.check_assign_dest(l_val);
+                let value = self.check_exp(exp);
+                self.require_number(value, exp.span());
+            }
+            Stmt::RawExp { exp, .. } => {
+                // the only place where a void value could appear
+                if let Some(exp) = exp {
+                    self.check_exp(exp);
+                }
+            }
+            Stmt::Block { block } => {
+                self.table.enter_scope();
+                self.check_block_items(block);
+                self.table.exit_scope();
+            }
+            Stmt::If {
+                cond,
+                then_stmt,
+                else_stmt,
+                ..
+            } => {
+                let value = self.check_exp(cond);
+                self.require_number(value, cond.span());
+                self.check_stmt(then_stmt);
+                if let Some(else_stmt) = else_stmt {
+                    self.check_stmt(else_stmt);
+                }
+            }
+            Stmt::While { cond, body, .. } => {
+                let value = self.check_exp(cond);
+                self.require_number(value, cond.span());
+                self.loop_depth += 1;
+                self.check_stmt(body);
+                self.loop_depth -= 1;
+            }
+            Stmt::Break { span } if self.loop_depth == 0 => {
+                self.error(ErrorKind::BreakOutsideLoop.at(*span))
+            }
+            Stmt::Continue { span } if self.loop_depth == 0 => {
+                self.error(ErrorKind::ContinueOutsideLoop.at(*span))
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::ReturnStmt { exp, span } => match (exp, &self.ret_type) {
+                (Some(exp), BType::Void) => {
+                    self.check_exp(exp);
+                    self.error(ErrorKind::ReturnValueInVoidFunc.at(*span));
+                }
+                (Some(exp), _) => {
+                    let value = self.check_exp(exp);
+                    self.require_number(value, exp.span());
+                }
+                (None, BType::Void) => {}
+                (None, _) => self.error(ErrorKind::MissingReturnValue.at(*span)),
+            },
+            Stmt::Error { .. } => {}
+        }
+    }
+
+    /// check the left side of an assignment, which must be an int or float variable.
+    fn check_assign_dest(&mut self, l_val: &LVal) {
+        if let Some((id, typ)) = self.check_lval(l_val) {
+            match (&self.table.get(id).kind, typ) {
+                (SymbolKind::Const(_), _) => {
+                    self.error(ErrorKind::AssignToConst(l_val.ident.clone()).at(l_val.span))
+                }
+                (_, BType::Int | BType::Float) => {}
+                _ => self.error(ErrorKind::AssignToArray(l_val.ident.clone()).at(l_val.span)),
+            }
+        }
+    }
+
+    /// resolve the identifier of l_val and check its indices.
+    /// @return the symbol and the type of the element (or sub-array) referred
+    fn check_lval(&mut self, l_val: &LVal) -> Option<(SymbolId, BType)> {
+        for index in &l_val.indices {
+            let value = self.check_exp(index);
+            self.require_int(value, index.span());
+        }
+
+        let id = match self.table.lookup(&l_val.ident) {
+            Some(id) => id,
+            None => {
+                self.error(ErrorKind::UndeclaredIdent(l_val.ident.clone()).at(l_val.span));
+                return None;
+            }
+        };
+        self.info.resolutions.insert(l_val.id, id);
+        self.table.get_mut(id).uses.push(l_val.span);
+
+        let mut typ = self.table.get(id).typ.clone();
+        for _ in &l_val.indices {
+            typ = match typ {
+                BType::Array(elem, _) | BType::Pointer(elem) => *elem,
+                _ => {
+                    self.error(ErrorKind::NotAnArray(l_val.ident.clone()).at(l_val.span));
+                    return None;
+                }
+            };
+        }
+        Some((id, typ))
+    }
+
+    /// check an expression which must be evaluated at compile time.
+    fn check_const_exp(&mut self, exp: &Expr) -> Option<ConstValue> {
+        let value = self.check_exp(exp);
+        let value = self.require_number(value, exp.span())?;
+        self.require_const(value.value)
+    }
+
+    /// report why the value isn't known at compile time.
+    fn require_const(&mut self, value: Result<ConstValue, CompileError>) -> Option<ConstValue> {
+        match value {
+            Ok(value) => Some(value),
+            Err(e) => {
+                if let ErrorKind::DivisionByZero = e.kind {
+                    self.divisions_by_zero.retain(|span| Some(*span) != e.span);
+                }
+                self.error(e);
+                None
+            }
+        }
+    }
+
+    /// report the value unless it's an int.
+    fn require_int(&mut self, value: Option<ExpValue>, span: Span) -> Option<ExpValue> {
+        let value = self.require_number(value, span)?;
+        match value.typ {
+            BType::Int => Some(value),
+            found => {
+                self.error(
+                    ErrorKind::MismatchedTypes {
+                        expected: BType::Int,
+                        found,
+                    }
+                    .at(span),
+                );
+                None
+            }
+        }
+    }
+
+    /// report the value unless it's an int or a float, which are converted to each other implicitly.
+    fn require_number(&mut self, value: Option<ExpValue>, span: Span) -> Option<ExpValue> {
+        let value = value?;
+        match value.typ {
+            BType::Int | BType::Float => Some(value),
+            BType::Void => {
+                self.error(ErrorKind::VoidValue.at(span));
+                None
+            }
+            typ => {
+                self.error(
+                    ErrorKind::MismatchedTypes {
+                        expected: BType::Int,
+                        found: typ,
+                    }
+                    .at(span),
+                );
+                None
+            }
+        }
+    }
+
+    /// remember the constant value of the expression for the lowering.
+    fn record(&mut self, node: NodeId, value: ExpValue) -> Option<ExpValue> {
+        if let Ok(v) = value.value {
+            self.info.values.insert(node, v);
+        }
+        Some(value)
+    }
+
+    /// check both operands of a binary operator, and fold them if possible.
+    fn check_binary(
+        &mut self,
+        span: Span,
+        lhs: (Option<ExpValue>, Span),
+        rhs: (Option<ExpValue>, Span),
+        op: &BinaryOp,
+    ) -> Option<ExpValue> {
+        let (lhs_span, rhs_span) = (lhs.1, rhs.1);
+        let lhs = self.require_number(lhs.0, lhs_span);
+        let rhs = self.require_number(rhs.0, rhs_span);
+        let (lhs, rhs) = (lhs?, rhs?);
+
+        // operands are converted to float if either of them is float
+        let (common, float_span) = match (&lhs.typ, &rhs.typ) {
+            (BType::Float, _) => (BType::Float, lhs_span),
+            (_, BType::Float) => (BType::Float, rhs_span),
+            _ => (BType::Int, span),
+        };
+        let typ = match op.result_type(&common) {
+            Some(typ) => typ,
+            None => {
+                self.error(
+                    ErrorKind::MismatchedTypes {
+                        expected: BType::Int,
+                        found: common,
+                    }
+                    .at(float_span),
+                );
+                return None;
+            }
+        };
+
+        // the left operand is folded first, for it may decide the result of && and ||
+        let value = match (lhs.value, rhs.value) {
+            (Ok(l), rhs) => match op.short_circuit(l) {
+                Some(value) => Ok(value),
+                None => rhs.and_then(|r| {
+                    op.eval(l, r).map_err(|e| {
+                        self.divisions_by_zero.push(span);
+                        e.at(span)
+                    })
+                }),
+            },
+            (Err(e), _) => Err(e),
+        };
+        Some(ExpValue { typ, value })
+    }
+
+    fn check_exp(&mut self, exp: &Expr) -> Option<ExpValue> {
+        // chains like a + b + c nest on the left as deep as they're long, so the
+        // binary operators on the left spine are checked with a worklist, innermost first
+        let mut spine = vec![];
+        let mut leaf = exp;
+        while let Expr::Binary { op, lhs, rhs, span, id } = leaf {
+            spine.push((op, lhs.span(), rhs, *span, *id));
+            leaf = lhs;
+        }
+
+        let mut value = self.check_operand(leaf);
+        for (op, lhs_span, rhs, span, id) in spine.into_iter().rev() {
+            let rhs = (self.check_exp(rhs), rhs.span());
+            value = self
+                .check_binary(span, (value, lhs_span), rhs, op)
+                .and_then(|v| self.record(id, v));
+        }
+        value
+    }
+
+    /// check an expression other than a binary operator.
+    fn check_operand(&mut self, exp: &Expr) -> Option<ExpValue> {
+        let value = match exp {
+            Expr::Binary { .. } => unreachable!("binary operators are checked by check_exp"),
+            Expr::Unary { op, exp, .. } => {
+                let value = self.check_exp(exp);
+                let value = self.require_number(value, exp.span())?;
+                let typ = match op {
+                    UnaryOp::Not => BType::Int,
+                    _ => value.typ,
+                };
+                Some(ExpValue {
+                    typ,
+                    value: value.value.map(|v| op.eval(v)),
+                })
+            }
+            Expr::Call { ident, args, span, .. } => self.check_call(ident, args, *span),
+            Expr::Number { value, .. } => Some(ExpValue {
+                typ: BType::Int,
+                value: Ok(ConstValue::Int(*value)),
+            }),
+            Expr::FloatNumber { value, .. } => Some(ExpValue {
+                typ: BType::Float,
+                value: Ok(ConstValue::Float(*value)),
+            }),
+            Expr::LVal { l_val } => {
+                let (id, typ) = self.check_lval(l_val)?;
+                let symbol = self.table.get(id);
+
+                let value = match &symbol.kind {
+                    // a constant with errors has been reported
+                    SymbolKind::Const(values) if values.is_empty() => return None,
+                    SymbolKind::Const(values) => self.fold_const_elem(l_val, &symbol.typ, values),
+                    SymbolKind::Var | SymbolKind::Param => {
+                        Err(ErrorKind::NonConstInitializer(format!("`{}`", l_val.ident)).at(l_val.span))
+                    }
+                };
+
+                // partially indexed array decays to pointer to its first element
+                let typ = match typ {
+                    BType::Array(elem, _) => BType::Pointer(elem),
+                    typ => typ,
+                };
+                Some(ExpValue { typ, value })
+            }
+        };
+        self.record(exp.id(), value?)
+    }
+
+    /// check the arguments against the params of the callee.
+    fn check_call(&mut self, ident: &str, args: &[Expr], span: Span) -> Option<ExpValue> {
+        let args = args
+            .iter()
+            .map(|arg| (self.check_exp(arg), arg.span()))
+            .collect::<Vec<_>>();
+
+        let func = match self.table.lookup_func(ident) {
+            Some(func) => func.clone(),
+            None => {
+                self.error(ErrorKind::UndeclaredFunc(ident.to_string()).at(span));
+                return None;
+            }
+        };
+        if func.param_types.len() != args.len() {
+            self.error(
+                ErrorKind::ArgumentCount {
+                    func: ident.to_string(),
+                    expected: func.param_types.len(),
+                    found: args.len(),
+                }
+                .at(span),
+            );
+        }
+
+        for ((arg, arg_span), param_type) in args.into_iter().zip(&func.param_types) {
+            match (arg, param_type) {
+                (arg, BType::Int | BType::Float) => {
+                    self.require_number(arg, arg_span);
+                }
+                // arrays are passed as pointers, whose element types must be the same
+                (Some(arg), param_type) if arg.typ != *param_type => {
+                    let kind = match arg.typ {
+                        BType::Void => ErrorKind::VoidValue,
+                        found => ErrorKind::MismatchedTypes {
+                            expected: param_type.clone(),
+                            found,
+                        },
+                    };
+                    self.error(kind.at(arg_span));
+                }
+                _ => {}
+            }
+        }
+
+        Some(ExpValue {
+            typ: func.ret_type,
+            value: Err(ErrorKind::NonConstInitializer(format!("call to `{}`", ident))
+                .at(span)),
+        })
+    }
+
+    /// elements of constant arrays are folded as long as the indices are constant.
+    fn fold_const_elem(
+        &self,
+        l_val: &LVal,
+        typ: &BType,
+        values: &[ConstValue],
+    ) -> Result<ConstValue, CompileError> {
+        let dims = typ.get_dims();
+        if l_val.indices.len() != dims.len() {
+            return Err(ErrorKind::NonConstInitializer(format!(
+                "partially indexed array `{}`",
+                l_val.ident
+            ))
+            .at(l_val.span));
+        }
+
+        let mut offset = 0;
+        for (index_exp, len) in l_val.indices.iter().zip(dims) {
+            let index = self
+                .info
+                .value_of(index_exp.id())
+                .map(ConstValue::as_int)
+                .ok_or_else(|| {
+                    ErrorKind::NonConstInitializer("array index".to_string()).at(index_exp.span())
+                })?;
+            if index < 0 || index as u32 >= len {
+                return Err(ErrorKind::IndexOutOfBounds {
+                    ident: l_val.ident.clone(),
+                    index,
+                }
+                .at(index_exp.span()));
+            }
+            offset = offset * len as usize + index as usize;
+        }
+        Ok(values[offset])
+    }
+}
+
+/// check that every declaration and identifier of the AST is resolved by info, so
+/// that the lowering finds the symbols it needs. a pass that rewrites the AST after
+/// semantic analysis would break it.
+pub fn check_resolved(comp_unit: &CompUnit, info: &SemanticInfo) -> Result<(), Vec<CompileError>> {
+    check_node_ids(comp_unit).map_err(|e| vec![e])?;
+
+    let mut checker = ResolutionChecker { info, errors: vec![] };
+    checker.visit_comp_unit(comp_unit);
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+struct ResolutionChecker<'a> {
+    info: &'a SemanticInfo,
+    errors: Vec<CompileError>,
+}
+
+impl ResolutionChecker<'_> {
+    fn check(&mut self, node: NodeId, ident: &str, span: Span) {
+        if self.info.resolve(node).is_none() {
+            let message = format!("`{}` isn't resolved by semantic analysis", ident);
+            self.errors.push(ErrorKind::Internal(message).at(span));
+        }
+    }
+}
+
+impl Visitor for ResolutionChecker<'_> {
+    fn visit_func_f_param(&mut self, node: &FuncFParam) {
+        self.check(node.id, &node.ident, node.span);
+        self.walk_func_f_param(node)
+    }
+
+    fn visit_const_def(&mut self, node: &ConstDef) {
+        self.check(node.id, &node.ident, node.span);
+        self.walk_const_def(node)
+    }
+
+    fn visit_var_def(&mut self, node: &VarDef) {
+        self.check(node.id, &node.ident, node.span);
+        self.walk_var_def(node)
+    }
+
+    fn visit_l_val(&mut self, node: &LVal) {
+        self.check(node.id, &node.ident, node.span);
+        self.walk_l_val(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+    use crate::driver::driver::parse;
+    use crate::error::error::ErrorKind;
+    use crate::error::warning::WarningKind;
+
+    // errors of checking source, which is expected to parse
+    fn errors(source: &str) -> Vec<ErrorKind> {
+        match check(&parse(source).unwrap()) {
+            Ok(_) => vec![],
+            Err(diagnostics) => diagnostics.errors.into_iter().map(|e| e.kind).collect(),
+        }
+    }
+
+    #[test]
+    fn global_and_function_share_no_name() {
+        let redefined = |source| {
+            matches!(&errors(source)[..], [ErrorKind::Redefinition(ident)] if ident == "f")
+        };
+        assert!(redefined("int f; int f() { return 1; } int main() { return 0; }"));
+        assert!(redefined("int f() { return 1; } const int f = 1; int main() { return 0; }"));
+        assert!(redefined("int f() { return 1; } int f[2]; int main() { return 0; }"));
+        // locals could still shadow functions
+        assert!(errors("int f() { return 1; } int main() { int f = 2; return f; }").is_empty());
+    }
+
+    #[test]
+    fn names_are_visible_after_declaration() {
+        let undeclared = |source| matches!(&errors(source)[..], [ErrorKind::UndeclaredIdent(a)] if a == "a");
+        assert!(undeclared("int main() { return a; } int a = 1;"));
+        assert!(undeclared("int f() { return a; } int a = 1; int main() { return f(); }"));
+        assert!(errors("int a = 1; int main() { return a; }").is_empty());
+
+        let source = "int main() { return f(); } int f() { return 1; }";
+        assert!(matches!(&errors(source)[..], [ErrorKind::UndeclaredFunc(f)] if f == "f"));
+    }
+
+    #[test]
+    fn runtime_division_by_zero_is_warned() {
+        let warned = |source| {
+            let info = check(&parse(source).unwrap()).unwrap();
+            info.warnings.iter().filter(|w| matches!(w.kind, WarningKind::DivisionByZero)).count()
+        };
+        assert_eq!(warned("int main() { int a = 1 / 0; return a + 7 % (1 - 1); }"), 2);
+        assert_eq!(warned("int main() { int z = 0; return 1 / z; }"), 0);
+
+        // an error in constant expressions instead
+        let source = "const int c = 1 / 0; int main() { return c; }";
+        assert!(matches!(&errors(source)[..], [ErrorKind::DivisionByZero]));
+        let diagnostics = check(&parse(source).unwrap()).err().unwrap();
+        assert!(diagnostics.warnings.is_empty());
+    }
+}
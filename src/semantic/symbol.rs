@@ -0,0 +1,120 @@
+use crate::ast::const_eval::ConstValue;
+use crate::config::config::BType;
+use crate::error::error::ErrorKind;
+use crate::error::span::Span;
+
+use std::collections::HashMap;
+
+/// index of a symbol in SymbolTable::symbols
+pub type SymbolId = usize;
+
+#[derive(Debug, Clone)]
+pub enum SymbolKind {
+    // compile-time constant, holding the flattened values for arrays
+    Const(Vec<ConstValue>),
+    Var,
+    Param,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // array parameters are pointers to their first element
+    pub typ: BType,
+    pub decl_span: Span,
+    // identifiers referring to the symbol, including the assigned ones
+    pub uses: Vec<Span>,
+}
+
+/// signature of a function
+#[derive(Debug, Clone)]
+pub struct FuncSymbol {
+    pub name: String,
+    pub ret_type: BType,
+    pub param_types: Vec<BType>,
+}
+
+/// constants and variables in nested scopes, together with the global functions.
+pub struct SymbolTable {
+    // every symbol ever declared, so that ids stay valid after the scope exits
+    pub symbols: Vec<Symbol>,
+    // the first one is the global scope
+    scopes: Vec<HashMap<String, SymbolId>>,
+    funcs: HashMap<String, FuncSymbol>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable {
+            symbols: vec![],
+            scopes: vec![HashMap::new()],
+            funcs: HashMap::new(),
+        }
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn is_global_scope(&self) -> bool {
+        self.scopes.len() == 1
+    }
+
+    /// declare symbol in current scope, which shadows the ones in outer scopes.
+    /// globals share their names with functions, while locals could shadow them.
+    pub fn declare(&mut self, symbol: Symbol) -> Result<SymbolId, ErrorKind> {
+        if self.is_global_scope() && self.funcs.contains_key(&symbol.name) {
+            return Err(ErrorKind::Redefinition(symbol.name));
+        }
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.contains_key(&symbol.name) {
+            return Err(ErrorKind::Redeclaration(symbol.name));
+        }
+        let id = self.symbols.len();
+        scope.insert(symbol.name.clone(), id);
+        self.symbols.push(symbol);
+        Ok(id)
+    }
+
+    /// the symbol that name refers to in current scope.
+    pub fn lookup(&self, name: &str) -> Option<SymbolId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    pub fn get(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id]
+    }
+
+    pub fn get_mut(&mut self, id: SymbolId) -> &mut Symbol {
+        &mut self.symbols[id]
+    }
+
+    pub fn declare_func(&mut self, func: FuncSymbol) -> Result<(), ErrorKind> {
+        if self.scopes[0].contains_key(&func.name) {
+            return Err(ErrorKind::Redefinition(func.name));
+        }
+        if self.funcs.contains_key(&func.name) {
+            return Err(ErrorKind::Redeclaration(func.name));
+        }
+        self.funcs.insert(func.name.clone(), func);
+        Ok(())
+    }
+
+    pub fn lookup_func(&self, name: &str) -> Option<&FuncSymbol> {
+        self.funcs.get(name)
+    }
+}
@@ -2,6 +2,8 @@ use std::fs::File;
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 
+// debugging helper without callers
+#[allow(dead_code)]
 pub fn redirect_stderr(path: &str) -> std::io::Result<()> {
     let file = File::create(path)?;
     let fd = file.as_raw_fd();
@@ -10,6 +12,8 @@ pub fn redirect_stderr(path: &str) -> std::io::Result<()> {
     Ok(())
 }
 
+// debugging helper without callers
+#[allow(dead_code)]
 pub fn get_abs_path(rel: &str) -> PathBuf {
     let src = std::path::Path::new(file!());
     src.join(rel)
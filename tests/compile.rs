@@ -0,0 +1,130 @@
+use sysy_compiler::error::error::ErrorKind;
+use sysy_compiler::error::warning::WarningKind;
+use sysy_compiler::{compile, Diagnostics, Options, Output, Stage};
+
+const SOURCE: &str = "
+int f(int x) {
+  if (x > 0) return x;
+}
+
+int main() {
+  int a;
+  if (f(1)) a = 1;
+  return a;
+}
+";
+
+fn compile_to(source: &str, stage: Stage) -> Result<Output, Diagnostics> {
+    compile(source, &Options { stage })
+}
+
+#[test]
+fn ast_stage_only_parses() {
+    let output = compile_to(SOURCE, Stage::Ast).unwrap();
+    assert_eq!(output.ast.items.len(), 2);
+    assert!(output.koopa.is_none());
+    assert!(output.asm.is_none());
+    // nothing is checked yet
+    assert!(output.warnings.is_empty());
+    // so a semantic error doesn't stop it
+    assert!(compile_to("int main() { return b; }", Stage::Ast).is_ok());
+}
+
+#[test]
+fn koopa_stage_lowers() {
+    let output = compile_to(SOURCE, Stage::Koopa).unwrap();
+    let koopa = format!("{}", output.koopa.unwrap());
+    assert!(koopa.contains("fun @main(): i32"));
+    assert!(koopa.contains("fun @f(%x: i32): i32"));
+    assert!(output.asm.is_none());
+}
+
+#[test]
+fn riscv_stage_generates_asm() {
+    let output = compile_to(SOURCE, Stage::Riscv).unwrap();
+    assert!(output.koopa.is_some());
+    let asm = format!("{}", output.asm.unwrap());
+    assert!(asm.contains("main:"));
+    assert!(asm.contains("call f"));
+}
+
+#[test]
+fn default_stage_is_riscv() {
+    assert_eq!(Options::default().stage, Stage::Riscv);
+}
+
+#[test]
+fn syntax_errors_are_reported() {
+    let diagnostics = compile_to("int main() { return 1 }", Stage::Riscv).err().unwrap();
+    assert!(!diagnostics.errors.is_empty());
+    for e in &diagnostics.errors {
+        assert!(matches!(e.kind, ErrorKind::Syntax(_)), "{}", e);
+    }
+    // no stage parses a malformed source
+    assert!(compile_to("int main() { return 1 }", Stage::Ast).is_err());
+}
+
+#[test]
+fn semantic_errors_are_reported_together() {
+    let source = "int main() { const int c = 1; c = 2; return b; }";
+    let diagnostics = compile_to(source, Stage::Koopa).err().unwrap();
+    assert_eq!(diagnostics.errors.len(), 2);
+    assert!(matches!(diagnostics.errors[0].kind, ErrorKind::AssignToConst(_)));
+    assert!(matches!(diagnostics.errors[1].kind, ErrorKind::UndeclaredIdent(_)));
+}
+
+#[test]
+fn warnings_are_carried_on_success() {
+    for stage in [Stage::Koopa, Stage::Riscv] {
+        let output = compile_to(SOURCE, stage).unwrap();
+        let kinds = output.warnings.iter().map(|w| &w.kind).collect::<Vec<_>>();
+        assert_eq!(kinds.len(), 2, "{:?}", kinds);
+        assert!(matches!(kinds[0], WarningKind::MissingReturn(f) if f == "f"));
+        assert!(matches!(kinds[1], WarningKind::MaybeUninitialized(a) if a == "a"));
+    }
+}
+
+#[test]
+fn warnings_are_carried_on_failure() {
+    let source = "
+int f() {}
+
+int main() { return b; }
+";
+    let diagnostics = compile_to(source, Stage::Riscv).err().unwrap();
+    assert_eq!(diagnostics.errors.len(), 1);
+    assert_eq!(diagnostics.warnings.len(), 1);
+    assert!(matches!(&diagnostics.warnings[0].kind, WarningKind::MissingReturn(f) if f == "f"));
+}
+
+#[test]
+fn branches_skip_over_jumps() {
+    let output = compile_to(SOURCE, Stage::Riscv).unwrap();
+    let asm = format!("{}", output.asm.unwrap());
+    let lines = asm.lines().map(str::trim).collect::<Vec<_>>();
+    let branches = lines.iter().enumerate().filter(|(_, l)| l.starts_with("beqz"));
+    for (i, branch) in branches {
+        // beqz only reaches the label right after the jump to the true target
+        let skip = branch.rsplit(' ').next().unwrap();
+        assert!(lines[i + 1].starts_with("j "), "{}", lines[i + 1]);
+        assert_eq!(lines[i + 2], format!("{}:", skip));
+        assert!(lines[i + 3].starts_with("j "), "{}", lines[i + 3]);
+    }
+    assert!(asm.contains("beqz"));
+    assert!(!asm.contains("bnez"));
+}
+
+#[test]
+fn long_chains_fit_in_a_small_stack() {
+    // a chain of 10k operands nests 10k binary operators deep on the left
+    let sum = vec!["x"; 10000].join(" + ");
+    let source = format!("int main() {{ int x = getint(); return {}; }}", sum);
+    // every pass walks it in a loop, so a small fixed stack is enough
+    let compiled = std::thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(move || compile_to(&source, Stage::Riscv).is_ok())
+        .unwrap()
+        .join()
+        .unwrap();
+    assert!(compiled);
+}
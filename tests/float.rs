@@ -0,0 +1,115 @@
+use sysy_compiler::{compile, Options, Stage};
+
+// riscv asm of source, one trimmed instruction or label per line
+fn asm(source: &str) -> Vec<String> {
+    let output = compile(source, &Options { stage: Stage::Riscv }).unwrap();
+    format!("{}", output.asm.unwrap())
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect()
+}
+
+// lines of func, from its label till the next function
+fn func<'a>(asm: &'a [String], name: &str) -> &'a [String] {
+    let start = asm.iter().position(|line| *line == format!("{}:", name)).unwrap();
+    let end = asm[start + 1..]
+        .iter()
+        .position(|line| line.ends_with(':') && !line.starts_with(".L"))
+        .map_or(asm.len(), |len| start + 1 + len);
+    &asm[start..end]
+}
+
+// the instructions moving arguments into place before `call name`
+fn before_call<'a>(asm: &'a [String], name: &str, len: usize) -> &'a [String] {
+    let call = asm.iter().position(|line| *line == format!("call {}", name)).unwrap();
+    &asm[call - len..call]
+}
+
+#[test]
+fn arithmetic() {
+    let asm = asm("int main() { float x = getfloat(), y = getfloat(); putfloat((x + y) * (x - y) / y); return 0; }");
+    for opcode in ["fadd.s", "fsub.s", "fmul.s", "fdiv.s"] {
+        assert!(asm.iter().any(|line| line.starts_with(opcode)), "{}", opcode);
+    }
+}
+
+#[test]
+fn comparisons() {
+    // a > b is b < a, a >= b is b <= a, and a != b is !(a == b)
+    for (op, expected) in [
+        ("<", "flt.s x5, f0, f1"),
+        (">", "flt.s x5, f1, f0"),
+        ("<=", "fle.s x5, f0, f1"),
+        (">=", "fle.s x5, f1, f0"),
+        ("==", "feq.s x5, f0, f1"),
+        ("!=", "feq.s x5, f0, f1"),
+    ] {
+        let source = format!("int main() {{ float x = getfloat(), y = getfloat(); return x {} y; }}", op);
+        let asm = asm(&source);
+        let i = asm.iter().position(|line| *line == expected).unwrap_or_else(|| panic!("{}", op));
+        assert_eq!(asm[i + 1] == "seqz x5, x5", op == "!=", "{}", op);
+    }
+}
+
+#[test]
+fn conversions() {
+    let asm = asm("int main() { float x = getfloat(); int n = x; float y = n; putfloat(y); return n; }");
+    // float to int truncates toward zero like C
+    assert!(asm.iter().any(|line| line.starts_with("fcvt.w.s") && line.ends_with(", rtz")));
+    assert!(asm.iter().any(|line| line.starts_with("fcvt.s.w")));
+}
+
+#[test]
+fn floats_are_passed_in_fa_registers() {
+    let asm = asm("
+float f(float a, int b, float c, int d) { return a + c; }
+int main() { putfloat(f(1.5, 2, 3.5, 4)); return 0; }
+");
+    // the callee saves fa0, a0, fa1, a1 in the order of the params
+    let callee = func(&asm, "f");
+    assert_eq!(callee[2], "fsw f10, 0(x2)");
+    assert_eq!(callee[3], "sw x10, 4(x2)");
+    assert_eq!(callee[4], "fsw f11, 8(x2)");
+    assert_eq!(callee[5], "sw x11, 12(x2)");
+    // the result is returned in fa0
+    let ret = callee.iter().position(|line| line == "ret").unwrap();
+    assert!(callee[ret - 2].starts_with("flw f10, "), "{}", callee[ret - 2]);
+
+    let args = before_call(&asm, "f", 6);
+    for arg in ["fmv.w.x f10, x5", "fmv.w.x f11, x5", "li x10, 2", "li x11, 4"] {
+        assert!(args.iter().any(|line| line == arg), "{:?}", args);
+    }
+    let fa0 = args.iter().position(|line| line == "fmv.w.x f10, x5").unwrap();
+    assert_eq!(args[fa0 - 1], format!("li x5, {}", 1.5f32.to_bits()));
+}
+
+#[test]
+fn ninth_float_is_passed_in_a0() {
+    let asm = asm("
+float f(float a, float b, float c, float d, float e, float g, float h, float i, float j) { return j; }
+int main() { putfloat(f(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.5)); return 0; }
+");
+    // once fa0-fa7 run out, floats are passed like ints
+    assert_eq!(before_call(&asm, "f", 1)[0], format!("li x10, {}", 9.5f32.to_bits()));
+    assert_eq!(func(&asm, "f")[10], "sw x10, 32(x2)");
+}
+
+#[test]
+fn float_spills_to_stack_after_all_registers() {
+    let asm = asm("
+float f(int a, int b, int c, int d, int e, int g, int h, int i,
+        float f1, float f2, float f3, float f4, float f5, float f6, float f7, float f8, float f9) {
+  return f9;
+}
+int main() { putfloat(f(1, 2, 3, 4, 5, 6, 7, 8, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.5)); return 0; }
+");
+    // the caller stores the 9th float at the bottom of its frame
+    let main = func(&asm, "main");
+    let store = main.iter().position(|line| line == "sw x5, 0(x2)").unwrap();
+    assert_eq!(main[store - 1], format!("li x5, {}", 9.5f32.to_bits()));
+
+    // and the callee finds it right above its own frame
+    let callee = func(&asm, "f");
+    let frame = callee[1].strip_prefix("addi x2, x2, -").unwrap();
+    assert!(callee.contains(&format!("lw x5, {}(x2)", frame)), "{:?}", callee);
+}
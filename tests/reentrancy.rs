@@ -0,0 +1,57 @@
+use sysy_compiler::{compile, Options};
+
+const FIRST: &str = "
+int g = 1;
+
+int add(int a, int b) {
+  return a + b;
+}
+
+int main() {
+  int i = 0, sum = 0;
+  while (i < 10) {
+    if (i % 2 == 0) sum = add(sum, i);
+    else sum = sum - g;
+    i = i + 1;
+  }
+  return sum;
+}
+";
+
+const SECOND: &str = "
+int arr[3] = {1, 2, 3};
+
+int main() {
+  int x = arr[1];
+  if (x > 1 && arr[2] < 4) {
+    x = x * 2;
+  }
+  return x;
+}
+";
+
+// koopa ir and asm of one compilation
+fn outputs(source: &str) -> (String, String) {
+    let output = compile(source, &Options::default()).unwrap();
+    (
+        format!("{}", output.koopa.unwrap()),
+        format!("{}", output.asm.unwrap()),
+    )
+}
+
+#[test]
+fn same_source_twice() {
+    let first = outputs(FIRST);
+    let again = outputs(FIRST);
+    assert_eq!(first, again);
+}
+
+#[test]
+fn different_sources_in_sequence() {
+    let first = outputs(FIRST);
+    let second = outputs(SECOND);
+    // no state of the first compilation leaks into the second
+    assert_eq!(outputs(FIRST), first);
+    assert_eq!(outputs(SECOND), second);
+    assert_eq!(outputs(FIRST), first);
+}